@@ -0,0 +1,158 @@
+use crate::db::{Archive, Candidate, PackageFamily, Suite};
+use crate::errors::*;
+use crate::registry::{IndexedPackage, find_packages, parse_packages};
+use flate2::read::GzDecoder;
+use std::fs;
+use std::io::{BufReader, Read};
+use std::sync::Arc;
+
+// mirrored indices of a whole archive are far larger than ureq's default limit
+const DOWNLOAD_LIMIT: u64 = 512 * 1024 * 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// The `Packages` index of an APT repository, e.g. an internal one publishing
+/// its own `librust-*-dev` packages
+#[derive(Debug, Clone)]
+pub struct AptIndex {
+    /// Path or URL the index was read from
+    pub location: String,
+    // shared by the connections of all workers
+    packages: Arc<Vec<IndexedPackage>>,
+}
+
+impl AptIndex {
+    /// Read a `Packages`, `Packages.gz` or `Packages.xz` index from a file or
+    /// an `http(s)://` URL
+    pub fn load(location: &str) -> Result<Self, Error> {
+        let buf = if location.starts_with("http://") || location.starts_with("https://") {
+            info!("Downloading apt index: {location}");
+            ureq::get(location)
+                .call()
+                .and_then(|mut response| {
+                    response
+                        .body_mut()
+                        .with_config()
+                        .limit(DOWNLOAD_LIMIT)
+                        .read_to_vec()
+                })
+                .with_context(|| format!("error downloading apt index {location:?}"))?
+        } else {
+            fs::read(location).with_context(|| format!("error reading apt index {location:?}"))?
+        };
+
+        let buf = decompress(buf)
+            .with_context(|| format!("error decompressing apt index {location:?}"))?;
+        let buf = String::from_utf8(buf)
+            .with_context(|| format!("apt index {location:?} is not valid utf-8"))?;
+
+        Ok(Self::parse(location, &buf))
+    }
+
+    pub(crate) fn parse(location: &str, buf: &str) -> Self {
+        AptIndex {
+            location: location.to_string(),
+            packages: Arc::new(parse_packages(buf, false)),
+        }
+    }
+}
+
+impl Archive for AptIndex {
+    fn candidates(
+        &mut self,
+        family: &PackageFamily,
+        suite: &Suite,
+    ) -> Result<Vec<Candidate>, Error> {
+        Ok(find_packages(&self.packages, family, suite))
+    }
+}
+
+// Repositories serve their indices compressed under the same name, so go by
+// the content instead of the file extension
+fn decompress(buf: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    if buf.starts_with(GZIP_MAGIC) {
+        GzDecoder::new(&buf[..]).read_to_end(&mut decompressed)?;
+    } else if buf.starts_with(XZ_MAGIC) {
+        lzma_rs::xz_decompress(&mut BufReader::new(&buf[..]), &mut decompressed)
+            .map_err(|err| anyhow!("{err:?}"))?;
+    } else {
+        return Ok(buf);
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AptIndex;
+    use crate::db::{Archive, Candidate, Distribution, PackageFamily, PkgType, Suite};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+
+    const PACKAGES: &str = "\
+Package: librust-acme-telemetry-dev
+Source: rust-acme-telemetry
+Version: 0.3.1-1+acme1
+Provides: librust-acme-telemetry-0.3-dev (= 0.3.1-1+acme1)
+Filename: pool/internal/r/rust-acme-telemetry/librust-acme-telemetry-dev_0.3.1-1+acme1_amd64.deb
+
+Package: acme-agent
+Version: 1.0.0-1
+Filename: pool/internal/a/acme-agent/acme-agent_1.0.0-1_amd64.deb
+";
+
+    #[test]
+    fn read_packages_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Packages.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(PACKAGES.as_bytes()).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let mut index = AptIndex::load(path.to_str().unwrap()).unwrap();
+        let family = PackageFamily::new("acme-telemetry", PkgType::Binary, None);
+        let candidates = index.candidates(&family, &Suite::New).unwrap();
+        assert_eq!(candidates, vec![]);
+        let suite = Suite::Release {
+            distribution: Distribution::Debian,
+            series: "sid".to_string(),
+        };
+        let candidates = index.candidates(&family, &suite).unwrap();
+        assert_eq!(
+            candidates,
+            vec![Candidate {
+                version: "0.3.1-1+acme1".to_string(),
+                source: Some("rust-acme-telemetry".to_string()),
+                binary: Some("librust-acme-telemetry-dev".to_string()),
+                suite: None,
+                component: Some("internal".to_string()),
+            }]
+        );
+
+        assert!(AptIndex::load(dir.path().join("missing").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn detect_index_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(PACKAGES.as_bytes()).unwrap();
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut PACKAGES.as_bytes(), &mut xz).unwrap();
+
+        // mirrors don't always keep the extension, e.g. when caching by URL
+        for (name, buf) in [
+            ("Packages", PACKAGES.as_bytes().to_vec()),
+            ("Packages.xz", xz),
+            ("index", gzip.finish().unwrap()),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, buf).unwrap();
+            let index = AptIndex::load(path.to_str().unwrap()).unwrap();
+            assert_eq!(index.packages.len(), 1, "{name}");
+        }
+    }
+}
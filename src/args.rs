@@ -1,4 +1,13 @@
+use crate::config::{Annotation, CrateConfig};
+use crate::db::Distribution;
+use crate::debian::{LookupOptions, PackagingProgress};
+use crate::graph::GraphOptions;
+use crate::pool::RETRIES;
+use crate::registry::{DPKG_STATUS, REGISTRY_DIR};
+use cargo_metadata::DependencyKind;
 use clap::{ArgAction, Parser, ValueEnum};
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -32,6 +41,27 @@ impl Display for ColorMode {
     }
 }
 
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeKind {
+    /// Regular dependencies
+    Normal,
+    /// Build dependencies
+    Build,
+    /// Development dependencies
+    Dev,
+}
+
+impl From<EdgeKind> for DependencyKind {
+    fn from(kind: EdgeKind) -> DependencyKind {
+        match kind {
+            EdgeKind::Normal => DependencyKind::Normal,
+            EdgeKind::Build => DependencyKind::Build,
+            EdgeKind::Dev => DependencyKind::Development,
+        }
+    }
+}
+
 #[derive(Parser, Clone)]
 pub struct Args {
     #[clap(long = "package", short = 'p', value_name = "SPEC")]
@@ -58,36 +88,154 @@ pub struct Args {
     #[clap(long = "all-targets")]
     /// Return dependencies for all targets. By default only the host target is matched.
     pub all_targets: bool,
-    #[clap(long = "skip-cache")]
+    #[clap(
+        long = "skip-cache",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
     /// Do not read from disk cache for Debian database results
     pub skip_cache: bool,
+    #[clap(long = "cache-dir", value_name = "PATH")]
+    /// Directory for the Debian database results cache
+    pub cache_dir: Option<PathBuf>,
+    #[clap(long = "cache-expire", value_name = "MINUTES")]
+    /// How long cached Debian database results stay valid [default: 90]
+    pub cache_expire: Option<u64>,
+    #[clap(long = "database-url", value_name = "URL")]
+    /// PostgreSQL connection string for the Debian database [default: public UDD mirror]
+    pub database_url: Option<String>,
+    #[clap(long = "distribution", value_name = "NAME", default_value = "debian")]
+    /// Archive to check the crates against: debian, ubuntu
+    pub distribution: Distribution,
+    #[clap(long = "series", value_name = "NAME")]
+    /// Release of the distribution, e.g. `trixie` or `noble` [default: sid for debian]
+    pub series: Option<String>,
+    #[clap(long = "apt-index", value_name = "PATH|URL")]
+    /// Also check the `Packages` index of an APT repository, can be given multiple times
+    pub apt_index: Vec<String>,
+    #[clap(long = "local")]
+    /// Check the crates available on this system instead of the Debian archive
+    pub local: bool,
+    #[clap(long = "registry-dir", value_name = "PATH", default_value = REGISTRY_DIR)]
+    /// Cargo registry of packaged crates, used with --local
+    pub registry_dir: PathBuf,
+    #[clap(
+        long = "dpkg-status",
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = DPKG_STATUS
+    )]
+    /// Also use the installed librust-*-dev packages with --local
+    pub dpkg_status: Option<PathBuf>,
+    #[clap(long = "debcargo-conf", value_name = "PATH")]
+    /// Check a debcargo-conf checkout for crates that are being packaged
+    pub debcargo_conf: Option<PathBuf>,
+    #[clap(
+        long = "testing",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    /// Check packaged crates for RC bugs and testing migration blockers
+    pub testing: bool,
+    #[clap(long = "wnpp-file", value_name = "PATH")]
+    /// Read ITP/RFP bugs from a `source: TYPE bug [owner]` file instead of the database
+    pub wnpp_file: Option<PathBuf>,
     #[clap(long = "concurrency", short = 'j', default_value = "24")]
     /// How many database connections to use concurrently
     pub concurrency: usize,
-    #[clap(long = "no-dev-dependencies")]
-    /// Skip dev dependencies.
+    #[clap(long = "retries", value_name = "N", default_value_t = RETRIES)]
+    /// How often to retry a lookup after a connection error or an overloaded database
+    pub retries: u32,
+    #[clap(
+        long = "no-dev-dependencies",
+        hide = true,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    /// Skip dev dependencies (same as `--edges normal,build`)
     pub no_dev_dependencies: bool,
+    #[clap(
+        long = "edges",
+        value_name = "KINDS",
+        value_delimiter = ',',
+        default_value = "normal,build,dev"
+    )]
+    /// Comma-separated list of dependency kinds to display
+    pub edges: Vec<EdgeKind>,
     #[clap(long = "manifest-path", value_name = "PATH")]
     /// Path to Cargo.toml
     pub manifest_path: Option<PathBuf>,
-    #[clap(long = "collapse-workspace", short = 'w')]
+    #[clap(
+        long = "collapse-workspace",
+        short = 'w',
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
     /// Hide the dependency trees of workspace members which are dependencies of other members
     pub collapse_workspace: bool,
     #[clap(long = "invert", short = 'i')]
     /// Invert the tree direction
     pub invert: bool,
-    #[clap(long = "no-indent")]
+    #[clap(
+        long = "no-indent",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
     /// Display the dependencies as a list (rather than a tree)
     pub no_indent: bool,
-    #[clap(long = "prefix-depth")]
+    #[clap(
+        long = "prefix-depth",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
     /// Display the dependencies as a list (rather than a tree), but prefixed with the depth
     pub prefix_depth: bool,
+    #[clap(long = "depth", value_name = "DEPTH")]
+    /// Maximum display depth of the dependency tree
+    pub depth: Option<usize>,
+    #[clap(long = "prune", value_name = "SPEC")]
+    /// Prune the given package from the display of the dependency tree
+    pub prune: Vec<String>,
     #[clap(long = "all", short = 'a')]
     /// Don't truncate dependencies that have already been displayed
     pub all: bool,
-    #[clap(long = "json")]
+    #[clap(
+        long = "json",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
     /// Print package information as machine-readable output
     pub json: bool,
+    #[clap(long = "align-lockfile")]
+    /// Print `cargo update` commands that would make Cargo.lock use the versions in Debian
+    pub align_lockfile: bool,
+    #[clap(long = "simulate-build")]
+    /// Check if the project would resolve against only the crates packaged in Debian
+    pub simulate_build: bool,
+    #[clap(long = "status", value_name = "STATUS", value_delimiter = ',')]
+    /// Only show paths leading to crates with one of these comma-separated packaging statuses
+    pub status: Option<Vec<PackagingProgress>>,
     #[clap(long = "duplicate", short = 'd')]
     /// Show only dependencies which come in multiple versions (implies -i)
     pub duplicates: bool,
@@ -123,9 +271,48 @@ pub struct Args {
     #[clap(short = 'Z', value_name = "FLAG")]
     /// Unstable (nightly-only) flags to Cargo
     pub unstable_flags: Vec<String>,
+    #[clap(skip)]
+    /// Per-crate overrides from the configuration file
+    pub crates: BTreeMap<String, CrateConfig>,
+    #[clap(skip)]
+    /// Crates that are intentionally not packaged, from the configuration file
+    pub ignore: Vec<Annotation>,
+}
+
+impl Args {
+    /// What to include in the dependency graph according to the command line
+    pub fn graph_options(&self) -> GraphOptions {
+        let split = |list: &String| list.split(',').map(String::from).collect();
+        GraphOptions {
+            dependency_kinds: self
+                .edges
+                .iter()
+                .filter(|kind| !self.no_dev_dependencies || **kind != EdgeKind::Dev)
+                .map(|kind| (*kind).into())
+                .collect(),
+            included: self.included.as_ref().map(split),
+            excluded: self.excluded.as_ref().map(split),
+            collapse_workspace: self.collapse_workspace,
+            ignore: self.ignore.clone(),
+        }
+    }
+
+    /// How to look up crates according to the command line
+    pub fn lookup_options(&self) -> LookupOptions {
+        LookupOptions {
+            skip_cache: self.skip_cache,
+            testing: self.testing,
+            debcargo_conf: self.debcargo_conf.clone(),
+            concurrency: self.concurrency,
+            retries: self.retries,
+            quiet: self.quiet,
+            crates: self.crates.clone(),
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum Charset {
     Utf8,
     Ascii,
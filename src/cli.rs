@@ -0,0 +1,119 @@
+use crate::apt::AptIndex;
+use crate::args::{ColorMode, Opts};
+use crate::config::Config;
+use crate::db::Connection;
+use crate::errors::*;
+use crate::registry::LocalRegistry;
+use crate::{debian, graph, lockfile, metadata, resolve, tree, wnpp};
+use clap::{CommandFactory, FromArgMatches};
+use colored::control::set_override;
+use std::io;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Run the `cargo debstatus` subcommand with the arguments of this process
+pub fn run() -> Result<(), Error> {
+    let matches = Opts::command().get_matches();
+    let Opts::Tree(mut args) = Opts::from_arg_matches(&matches)?;
+    if args.color == ColorMode::Always {
+        set_override(true);
+    } else if args.color == ColorMode::Never {
+        set_override(false);
+    }
+    info!("Reading metadata");
+    let metadata = metadata::get(&args)?;
+    info!("Loading configuration");
+    let config = Config::load(&metadata)?;
+    if let Some((_, matches)) = matches.subcommand() {
+        config.apply(&mut args, matches);
+    }
+    info!("Building graph");
+    let mut graph = graph::build(&args.graph_options(), metadata)?;
+    let wnpp = args.wnpp_file.as_deref().map(wnpp::read_file).transpose()?;
+    let apt_indices = args
+        .apt_index
+        .iter()
+        .map(|location| AptIndex::load(location))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // stop the lookups on Ctrl-C and print what we have so far, a second one
+    // exits right away
+    let interrupted = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let interrupted = interrupted.clone();
+        move || {
+            if interrupted.swap(true, Ordering::SeqCst) {
+                process::exit(130);
+            }
+        }
+    })?;
+
+    let options = args.lookup_options();
+    if args.local {
+        info!("Populating with local registry data");
+        let registry = LocalRegistry::from_args(&args)?;
+        debian::populate_until(
+            &mut graph,
+            &options,
+            &|| {
+                let db = Connection::with_archive(registry.clone())
+                    .with_distribution(args.distribution, args.series.as_deref())?
+                    .with_apt_indices(apt_indices.clone());
+                Ok(match &wnpp {
+                    Some(bugs) => db.with_wnpp_bugs(bugs.clone()),
+                    None => db,
+                })
+            },
+            &interrupted,
+        )?;
+    } else {
+        info!("Populating with debian data");
+        debian::populate_until(
+            &mut graph,
+            &options,
+            &|| {
+                let db = Connection::from_args(&args)?.with_apt_indices(apt_indices.clone());
+                Ok(match &wnpp {
+                    Some(bugs) => db.with_wnpp_bugs(bugs.clone()),
+                    None => db,
+                })
+            },
+            &interrupted,
+        )?;
+    }
+    // the handler can't be removed again, but with the flag set Ctrl-C exits
+    // right away, like it would without a handler
+    let was_interrupted = interrupted.swap(true, Ordering::SeqCst);
+
+    if args.simulate_build {
+        info!("Resolving against debian");
+        let resolution = resolve::simulate(&graph);
+        resolve::print(&args, &resolution, &mut io::stdout())?;
+        if !resolution.is_ok() {
+            bail!(
+                "{} dependencies can't be satisfied with the crates in debian",
+                resolution.failures.len()
+            );
+        }
+        // an interruption is reported below
+        if !resolution.unknown.is_empty() && !was_interrupted {
+            bail!(
+                "{} dependencies couldn't be looked up, it's unknown if they can be satisfied",
+                resolution.unknown.len()
+            );
+        }
+    } else if args.align_lockfile {
+        info!("Printing lockfile updates");
+        lockfile::print(&args, &graph, &mut io::stdout())?;
+    } else {
+        info!("Printing graph");
+        tree::print(&args, &graph, &mut io::stdout())?;
+    }
+
+    if was_interrupted {
+        bail!("Interrupted, not all crates were looked up");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,424 @@
+use crate::args::{Args, Charset, EdgeKind};
+use crate::db::{Distribution, NameOverride};
+use crate::debian::PackagingProgress;
+use crate::errors::*;
+use cargo_metadata::Metadata;
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options read from `[workspace.metadata.debstatus]`, `[package.metadata.debstatus]`
+/// and `.cargo/debstatus.toml`, in increasing order of precedence
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub collapse_workspace: Option<bool>,
+    pub no_dev_dependencies: Option<bool>,
+    pub edges: Option<Vec<EdgeKind>>,
+    pub status: Option<Vec<PackagingProgress>>,
+    pub prune: Option<Vec<String>>,
+    pub depth: Option<usize>,
+    pub format: Option<String>,
+    pub charset: Option<Charset>,
+    pub json: Option<bool>,
+    pub no_indent: Option<bool>,
+    pub prefix_depth: Option<bool>,
+    pub skip_cache: Option<bool>,
+    pub testing: Option<bool>,
+    pub debcargo_conf: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    /// Cache lifetime in minutes
+    pub cache_expire: Option<u64>,
+    pub database_url: Option<String>,
+    pub distribution: Option<Distribution>,
+    pub series: Option<String>,
+    pub apt_index: Option<Vec<String>>,
+    pub concurrency: Option<usize>,
+    pub retries: Option<u32>,
+    pub crates: BTreeMap<String, CrateConfig>,
+    /// Crates that are intentionally not packaged
+    pub ignore: Vec<Annotation>,
+}
+
+/// A crate we don't intend to package, e.g. `[[package.metadata.debstatus.ignore]]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Annotation {
+    pub name: String,
+    /// Only versions matching this requirement are ignored
+    pub version: Option<VersionReq>,
+    pub reason: String,
+    #[serde(default)]
+    pub kind: AnnotationKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnnotationKind {
+    /// Not needed for Debian, e.g. vendored or dev-only tooling
+    #[default]
+    Ignored,
+    /// Removed from the dependencies with a patch in Debian
+    PatchedOut,
+}
+
+impl Annotation {
+    pub fn matches(&self, name: &str, version: &Version) -> bool {
+        self.name == name && self.version.as_ref().is_none_or(|req| req.matches(version))
+    }
+}
+
+/// Per-crate overrides, e.g. `[package.metadata.debstatus.crates.foo]`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CrateConfig {
+    /// Hide this crate and its dependencies from the output
+    pub prune: bool,
+    /// Debian source package shipping this crate, e.g. `rust-resvg`
+    pub debian_source: Option<String>,
+    /// Debian binary package shipping this crate, e.g. `librust-usvg-dev`
+    pub debian_binary: Option<String>,
+    /// Recommend porting the dependents to a newer version in Debian instead
+    /// of packaging the version in use next to it
+    pub patch_dependents: bool,
+}
+
+impl CrateConfig {
+    pub fn name_override(&self) -> Option<NameOverride> {
+        if self.debian_source.is_none() && self.debian_binary.is_none() {
+            return None;
+        }
+        Some(NameOverride {
+            source: self.debian_source.clone(),
+            binary: self.debian_binary.clone(),
+        })
+    }
+}
+
+impl Config {
+    /// Load the configuration of the workspace described by `metadata`
+    pub fn load(metadata: &Metadata) -> Result<Config, Error> {
+        let mut config = Config::default();
+
+        if let Some(value) = metadata.workspace_metadata.get("debstatus") {
+            let workspace = Config::deserialize(value)
+                .context("error parsing [workspace.metadata.debstatus]")?;
+            config = config.merge(workspace);
+        }
+
+        if let Some(value) = metadata
+            .root_package()
+            .and_then(|pkg| pkg.metadata.get("debstatus"))
+        {
+            let package =
+                Config::deserialize(value).context("error parsing [package.metadata.debstatus]")?;
+            config = config.merge(package);
+        }
+
+        let path = metadata
+            .workspace_root
+            .as_std_path()
+            .join(".cargo/debstatus.toml");
+        if path.exists() {
+            config = config.merge(Config::read(&path)?);
+        }
+
+        Ok(config.resolve_paths(metadata.workspace_root.as_std_path()))
+    }
+
+    /// Make relative paths relative to the workspace root instead of the
+    /// directory cargo-debstatus is run from, e.g. with `--manifest-path`
+    pub fn resolve_paths(mut self, root: &Path) -> Config {
+        self.debcargo_conf = self.debcargo_conf.map(|path| root.join(path));
+        self.cache_dir = self.cache_dir.map(|path| root.join(path));
+        if let Some(apt_index) = &mut self.apt_index {
+            for location in apt_index {
+                if !location.starts_with("http://") && !location.starts_with("https://") {
+                    *location = root.join(&*location).to_string_lossy().into_owned();
+                }
+            }
+        }
+        self
+    }
+
+    pub fn read(path: &Path) -> Result<Config, Error> {
+        let buf = fs::read_to_string(path)
+            .with_context(|| format!("error reading config file {path:?}"))?;
+        toml::from_str(&buf).with_context(|| format!("error parsing config file {path:?}"))
+    }
+
+    /// Combine two configs, with the options of `other` taking precedence
+    pub fn merge(self, other: Config) -> Config {
+        let mut crates = self.crates;
+        crates.extend(other.crates);
+        let mut ignore = self.ignore;
+        ignore.extend(other.ignore);
+
+        Config {
+            include: other.include.or(self.include),
+            exclude: other.exclude.or(self.exclude),
+            collapse_workspace: other.collapse_workspace.or(self.collapse_workspace),
+            no_dev_dependencies: other.no_dev_dependencies.or(self.no_dev_dependencies),
+            edges: other.edges.or(self.edges),
+            status: other.status.or(self.status),
+            prune: other.prune.or(self.prune),
+            depth: other.depth.or(self.depth),
+            format: other.format.or(self.format),
+            charset: other.charset.or(self.charset),
+            json: other.json.or(self.json),
+            no_indent: other.no_indent.or(self.no_indent),
+            prefix_depth: other.prefix_depth.or(self.prefix_depth),
+            skip_cache: other.skip_cache.or(self.skip_cache),
+            testing: other.testing.or(self.testing),
+            debcargo_conf: other.debcargo_conf.or(self.debcargo_conf),
+            cache_dir: other.cache_dir.or(self.cache_dir),
+            cache_expire: other.cache_expire.or(self.cache_expire),
+            database_url: other.database_url.or(self.database_url),
+            distribution: other.distribution.or(self.distribution),
+            series: other.series.or(self.series),
+            apt_index: other.apt_index.or(self.apt_index),
+            concurrency: other.concurrency.or(self.concurrency),
+            retries: other.retries.or(self.retries),
+            crates,
+            ignore,
+        }
+    }
+
+    /// Fill in options that haven't been set on the command line
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        // only consider flags and options that were explicitly given
+        let is_set = |id: &str| {
+            matches
+                .value_source(id)
+                .is_some_and(|source| source != ValueSource::DefaultValue)
+        };
+
+        if !is_set("included") {
+            args.included = self.include.map(|v| v.join(","));
+        }
+        if !is_set("excluded") {
+            args.excluded = self.exclude.map(|v| v.join(","));
+        }
+        if !is_set("edges")
+            && let Some(edges) = self.edges
+        {
+            args.edges = edges;
+        }
+        if !is_set("status") {
+            args.status = self.status;
+        }
+        if !is_set("prune")
+            && let Some(prune) = self.prune
+        {
+            args.prune = prune;
+        }
+        if !is_set("depth") {
+            args.depth = self.depth;
+        }
+        if !is_set("format")
+            && let Some(format) = self.format
+        {
+            args.format = format;
+        }
+        if !is_set("charset")
+            && let Some(charset) = self.charset
+        {
+            args.charset = charset;
+        }
+        if !is_set("cache_dir") {
+            args.cache_dir = self.cache_dir;
+        }
+        if !is_set("cache_expire") {
+            args.cache_expire = self.cache_expire;
+        }
+        if !is_set("debcargo_conf") {
+            args.debcargo_conf = self.debcargo_conf;
+        }
+        if !is_set("database_url") {
+            args.database_url = self.database_url;
+        }
+        if !is_set("distribution")
+            && let Some(distribution) = self.distribution
+        {
+            args.distribution = distribution;
+        }
+        if !is_set("series") {
+            args.series = self.series;
+        }
+        if !is_set("apt_index")
+            && let Some(apt_index) = self.apt_index
+        {
+            args.apt_index = apt_index;
+        }
+        if !is_set("concurrency")
+            && let Some(concurrency) = self.concurrency
+        {
+            args.concurrency = concurrency;
+        }
+        if !is_set("retries")
+            && let Some(retries) = self.retries
+        {
+            args.retries = retries;
+        }
+
+        // flags can be turned off on the command line with e.g. `--json=false`
+        let flags = [
+            (
+                "collapse_workspace",
+                self.collapse_workspace,
+                &mut args.collapse_workspace,
+            ),
+            (
+                "no_dev_dependencies",
+                self.no_dev_dependencies,
+                &mut args.no_dev_dependencies,
+            ),
+            ("json", self.json, &mut args.json),
+            ("no_indent", self.no_indent, &mut args.no_indent),
+            ("prefix_depth", self.prefix_depth, &mut args.prefix_depth),
+            ("skip_cache", self.skip_cache, &mut args.skip_cache),
+            ("testing", self.testing, &mut args.testing),
+        ];
+        for (id, value, flag) in flags {
+            if !is_set(id)
+                && let Some(value) = value
+            {
+                *flag = value;
+            }
+        }
+
+        args.crates = self.crates;
+        args.ignore = self.ignore;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, CrateConfig};
+    use crate::args::{Args, EdgeKind, Opts};
+    use crate::debian::PackagingProgress;
+    use clap::{CommandFactory, FromArgMatches};
+    use std::path::Path;
+
+    fn parse(argv: &[&str], config: Config) -> Args {
+        let matches = Opts::command().get_matches_from(argv);
+        let Opts::Tree(mut args) = Opts::from_arg_matches(&matches).unwrap();
+        let (_, matches) = matches.subcommand().unwrap();
+        config.apply(&mut args, matches);
+        args
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let config: Config = toml::from_str(
+            r#"
+            exclude = ["foo", "bar"]
+            format = "{n} {d}"
+            status = ["missing", "needs-update"]
+            concurrency = 4
+            no-dev-dependencies = true
+
+            [crates.winapi]
+            prune = true
+            "#,
+        )
+        .unwrap();
+
+        let args = parse(&["cargo", "debstatus"], config.clone());
+        assert_eq!(args.excluded.as_deref(), Some("foo,bar"));
+        assert_eq!(args.format, "{n} {d}");
+        assert_eq!(
+            args.status,
+            Some(vec![
+                PackagingProgress::Missing,
+                PackagingProgress::NeedsUpdate
+            ])
+        );
+        assert_eq!(args.concurrency, 4);
+        assert!(args.no_dev_dependencies);
+        assert_eq!(
+            args.crates["winapi"],
+            CrateConfig {
+                prune: true,
+                ..Default::default()
+            }
+        );
+
+        let args = parse(
+            &[
+                "cargo",
+                "debstatus",
+                "-f",
+                "{p}",
+                "-j",
+                "8",
+                "--exclude",
+                "baz",
+            ],
+            config.clone(),
+        );
+        assert_eq!(args.excluded.as_deref(), Some("baz"));
+        assert_eq!(args.format, "{p}");
+        assert_eq!(args.concurrency, 8);
+
+        // flags enabled in the config can be turned off again
+        let args = parse(
+            &["cargo", "debstatus", "--no-dev-dependencies=false"],
+            config.clone(),
+        );
+        assert!(!args.no_dev_dependencies);
+        let args = parse(&["cargo", "debstatus", "--no-dev-dependencies"], config);
+        assert!(args.no_dev_dependencies);
+    }
+
+    #[test]
+    fn merge_configs() {
+        let workspace: Config = toml::from_str(
+            r#"
+            edges = ["normal"]
+            format = "{p}"
+            "#,
+        )
+        .unwrap();
+        let local: Config = toml::from_str(r#"format = "{n}""#).unwrap();
+
+        let config = workspace.merge(local);
+        assert_eq!(config.edges, Some(vec![EdgeKind::Normal]));
+        assert_eq!(config.format.as_deref(), Some("{n}"));
+    }
+
+    #[test]
+    fn resolve_relative_paths() {
+        let config: Config = toml::from_str(
+            r#"
+            debcargo-conf = "../debcargo-conf"
+            cache-dir = "/var/cache/debstatus"
+            apt-index = ["dists/Packages.xz", "https://apt.example.com/Packages"]
+            "#,
+        )
+        .unwrap();
+
+        let config = config.resolve_paths(Path::new("/src/project"));
+        assert_eq!(
+            config.debcargo_conf.as_deref(),
+            Some(Path::new("/src/project/../debcargo-conf"))
+        );
+        assert_eq!(
+            config.cache_dir.as_deref(),
+            Some(Path::new("/var/cache/debstatus"))
+        );
+        assert_eq!(
+            config.apt_index,
+            Some(vec![
+                "/src/project/dists/Packages.xz".to_string(),
+                "https://apt.example.com/Packages".to_string(),
+            ])
+        );
+    }
+}
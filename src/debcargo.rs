@@ -0,0 +1,443 @@
+use crate::db::semver_bucket;
+use crate::debversion::DebVersion;
+use crate::errors::*;
+use cargo_metadata::DependencyKind;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A crate that is being packaged in a debcargo-conf checkout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Preparation {
+    /// Directory below `src/`, e.g. `rand-core-0.5`
+    pub dir: String,
+    /// Debian version of the most recent changelog entry
+    pub version: String,
+    /// Distribution of the most recent changelog entry, `UNRELEASED` until uploaded
+    pub distribution: String,
+}
+
+impl Preparation {
+    pub fn released(&self) -> bool {
+        self.distribution != "UNRELEASED"
+    }
+}
+
+/// Look for a crate in a debcargo-conf checkout, preferring the semver-versioned
+/// directory (`src/foo-0.3`) over the main one (`src/foo`)
+///
+/// Only packaging of a compatible version counts.
+pub fn find(checkout: &Path, name: &str, version: &Version) -> Result<Option<Preparation>, Error> {
+    let name = name.replace('_', "-").to_lowercase();
+    let bucket = semver_bucket(version);
+
+    for dir in [format!("{name}-{bucket}"), name] {
+        let changelog = checkout.join("src").join(&dir).join("debian/changelog");
+        if !changelog.exists() {
+            continue;
+        }
+
+        let buf = fs::read_to_string(&changelog)
+            .with_context(|| format!("error reading {changelog:?}"))?;
+        let (version, distribution) =
+            parse_changelog(&buf).with_context(|| format!("error parsing {changelog:?}"))?;
+
+        let upstream = version.parse::<DebVersion>()?.to_semver()?;
+        if semver_bucket(&upstream) != bucket {
+            debug!("Ignoring {dir} {version} in debcargo-conf, {bucket} is needed");
+            continue;
+        }
+
+        return Ok(Some(Preparation {
+            dir,
+            version,
+            distribution,
+        }));
+    }
+
+    Ok(None)
+}
+
+// The directory a crate is packaged in, regardless of the version being packaged
+fn crate_dir(checkout: &Path, name: &str, version: &Version) -> Option<PathBuf> {
+    let name = name.replace('_', "-").to_lowercase();
+    [format!("{name}-{}", semver_bucket(version)), name]
+        .into_iter()
+        .map(|dir| checkout.join("src").join(dir))
+        .find(|dir| dir.exists())
+}
+
+/// How a Debian patch changes a dependency in a crate's `Cargo.toml`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchedDependency {
+    /// The version requirement is replaced, e.g. relaxed to allow an older version
+    Requirement(VersionReq),
+    /// The dependency is dropped
+    Removed,
+}
+
+/// A dependency of a given kind, by the name of the crate it refers to
+pub type DependencyKey = (DependencyKind, String);
+
+/// Dependencies that Debian changes in a crate's `Cargo.toml` with the patches
+/// in `debian/patches`
+pub fn patched_requirements(
+    checkout: &Path,
+    name: &str,
+    version: &Version,
+) -> Result<HashMap<DependencyKey, PatchedDependency>, Error> {
+    let mut requirements = HashMap::new();
+    let Some(dir) = crate_dir(checkout, name, version) else {
+        return Ok(requirements);
+    };
+
+    let patches = dir.join("debian/patches");
+    let series = patches.join("series");
+    if !series.exists() {
+        return Ok(requirements);
+    }
+    let series =
+        fs::read_to_string(&series).with_context(|| format!("error reading {series:?}"))?;
+
+    // patches are applied in order, later ones win
+    for patch in series.lines() {
+        let Some(patch) = patch
+            .split_whitespace()
+            .next()
+            .filter(|p| !p.starts_with('#'))
+        else {
+            continue;
+        };
+        let path = patches.join(patch);
+        let buf = fs::read_to_string(&path).with_context(|| format!("error reading {path:?}"))?;
+        requirements.extend(parse_patch(&buf));
+    }
+
+    Ok(requirements)
+}
+
+// The lines of a dependency table within a hunk of a patch
+struct Table {
+    header: String,
+    /// Set if the header itself is removed by the patch
+    removed: bool,
+    /// Lines with their diff marker, ` `, `-` or `+`
+    lines: Vec<(char, String)>,
+}
+
+impl Table {
+    // Collect the changes to the dependencies listed in this table
+    fn changes(&self, changes: &mut HashMap<DependencyKey, PatchedDependency>) {
+        let Some((kind, name)) = dependency_table(&self.header) else {
+            return;
+        };
+        let parse = |marker: char| {
+            self.lines
+                .iter()
+                .filter(|(m, _)| *m == marker)
+                .filter_map(|(_, line)| toml::from_str::<toml::Table>(line).ok())
+                .flatten()
+                .collect::<Vec<_>>()
+        };
+        let (context, removed, added) = (parse(' '), parse('-'), parse('+'));
+
+        match name {
+            // `[dependencies.foo]` followed by `version = "0.3"`, the crate may
+            // be renamed with `package = "bar"`
+            Some(name) => {
+                let package = [&added, &context, &removed]
+                    .into_iter()
+                    .flatten()
+                    .find(|(key, _)| key == "package")
+                    .and_then(|(_, value)| value.as_str())
+                    .unwrap_or(name);
+                let key = (kind, package.to_string());
+                if self.removed {
+                    changes.insert(key, PatchedDependency::Removed);
+                } else if let Some(req) = added
+                    .iter()
+                    .find(|(key, _)| key == "version")
+                    .and_then(|(_, value)| parse_req(value))
+                {
+                    changes.insert(key, PatchedDependency::Requirement(req));
+                }
+            }
+            // `[dependencies]` followed by `foo = "0.3"` or `foo = { version = "0.3" }`
+            None => {
+                for (key, value) in &removed {
+                    if !added.iter().any(|(added, _)| added == key) {
+                        changes
+                            .insert((kind, package_name(key, value)), PatchedDependency::Removed);
+                    }
+                }
+                for (key, value) in &added {
+                    let req = match value {
+                        toml::Value::Table(table) => table.get("version").and_then(parse_req),
+                        value => parse_req(value),
+                    };
+                    if let Some(req) = req {
+                        changes.insert(
+                            (kind, package_name(key, value)),
+                            PatchedDependency::Requirement(req),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The crate a dependency refers to, which differs from its name if it's renamed
+fn package_name(key: &str, value: &toml::Value) -> String {
+    value
+        .get("package")
+        .and_then(|package| package.as_str())
+        .unwrap_or(key)
+        .to_string()
+}
+
+fn parse_req(value: &toml::Value) -> Option<VersionReq> {
+    VersionReq::parse(value.as_str()?).ok()
+}
+
+// Find the dependencies changed in `Cargo.toml` by a patch, either below
+// `[dependencies.foo]` or inline as `foo = "0.3"` below `[dependencies]`
+fn parse_patch(buf: &str) -> HashMap<DependencyKey, PatchedDependency> {
+    let mut changes = HashMap::new();
+    let mut in_manifest = false;
+    let mut table: Option<Table> = None;
+
+    for line in buf.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.split_whitespace().next().unwrap_or_default();
+            in_manifest = path.ends_with("/Cargo.toml") || path == "Cargo.toml";
+            if let Some(table) = table.take() {
+                table.changes(&mut changes);
+            }
+            continue;
+        }
+        if !in_manifest || line.starts_with("--- ") {
+            continue;
+        }
+        if line.starts_with("@@") {
+            // the hunk header doesn't tell which table we're in
+            if let Some(table) = table.take() {
+                table.changes(&mut changes);
+            }
+            continue;
+        }
+
+        let Some(marker) = line.chars().next() else {
+            continue;
+        };
+        let content = line[marker.len_utf8()..].trim();
+        if content.starts_with('[') {
+            if let Some(table) = table.take() {
+                table.changes(&mut changes);
+            }
+            table = Some(Table {
+                header: content.trim_matches(['[', ']']).to_string(),
+                removed: marker == '-',
+                lines: vec![],
+            });
+        } else if let Some(table) = &mut table {
+            table.lines.push((marker, content.to_string()));
+        }
+    }
+    if let Some(table) = table {
+        table.changes(&mut changes);
+    }
+
+    changes
+}
+
+// Check if a table header lists dependencies, returning their kind and the
+// dependency name for tables of a single dependency
+fn dependency_table(section: &str) -> Option<(DependencyKind, Option<&str>)> {
+    // strip `target.'cfg(unix)'.` and the like
+    let section = match section.strip_prefix("target.") {
+        Some(rest) => {
+            let end = rest.rfind("dependencies")?;
+            let start = rest[..end].rfind('.')? + 1;
+            &rest[start..]
+        }
+        None => section,
+    };
+
+    let (kind, name) = match section.split_once('.') {
+        Some((kind, name)) => (kind, Some(name.trim_matches('"'))),
+        None => (section, None),
+    };
+    let kind = match kind {
+        "dependencies" => DependencyKind::Normal,
+        "build-dependencies" | "build_dependencies" => DependencyKind::Build,
+        "dev-dependencies" | "dev_dependencies" => DependencyKind::Development,
+        _ => return None,
+    };
+    Some((kind, name))
+}
+
+// Read the version and distribution from the first line of a changelog, e.g.
+// `rust-foo (0.3.2-1) UNRELEASED; urgency=medium`
+fn parse_changelog(buf: &str) -> Result<(String, String), Error> {
+    let line = buf
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .context("changelog is empty")?;
+
+    let (_, rest) = line.split_once(" (").context("missing version")?;
+    let (version, rest) = rest.split_once(')').context("missing version")?;
+    let (distribution, _) = rest.split_once(';').context("missing distribution")?;
+    let distribution = distribution
+        .split_whitespace()
+        .next()
+        .context("missing distribution")?;
+
+    Ok((version.to_string(), distribution.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PatchedDependency, find, parse_changelog, parse_patch};
+    use cargo_metadata::DependencyKind;
+    use semver::{Version, VersionReq};
+    use std::collections::HashMap;
+    use std::fs;
+
+    #[test]
+    fn parse_changelog_header() {
+        let (version, distribution) = parse_changelog(
+            "rust-foo (0.3.2-1) UNRELEASED; urgency=medium\n\n  * Package foo 0.3.2\n",
+        )
+        .unwrap();
+        assert_eq!(version, "0.3.2-1");
+        assert_eq!(distribution, "UNRELEASED");
+
+        assert!(parse_changelog("").is_err());
+        assert!(parse_changelog("rust-foo 0.3.2-1 unstable").is_err());
+    }
+
+    #[test]
+    fn find_in_checkout() {
+        let checkout = tempfile::tempdir().unwrap();
+        let write = |dir: &str, header: &str| {
+            let path = checkout.path().join("src").join(dir).join("debian");
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("changelog"), header).unwrap();
+        };
+        write(
+            "rand-core",
+            "rust-rand-core (0.9.0-1) UNRELEASED; urgency=medium\n",
+        );
+        write(
+            "rand-core-0.5",
+            "rust-rand-core-0.5 (0.5.1-1) unstable; urgency=medium\n",
+        );
+
+        let prep = find(checkout.path(), "rand_core", &Version::new(0, 5, 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(prep.dir, "rand-core-0.5");
+        assert!(prep.released());
+
+        let prep = find(checkout.path(), "rand_core", &Version::new(0, 9, 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(prep.dir, "rand-core");
+        assert_eq!(prep.version, "0.9.0-1");
+        assert!(!prep.released());
+
+        assert_eq!(
+            find(checkout.path(), "rand_core", &Version::new(0, 6, 4)).unwrap(),
+            None
+        );
+        assert_eq!(
+            find(checkout.path(), "serde", &Version::new(1, 0, 0)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_relaxed_dependencies() {
+        let requirements = parse_patch(
+            r#"Index: foo/Cargo.toml
+===================================================================
+--- foo.orig/Cargo.toml
++++ foo/Cargo.toml
+@@ -30,10 +30,10 @@
+ [dependencies.bar]
+-version = "0.4"
++version = ">= 0.3, < 0.5"
+ features = ["std"]
+ 
+ [dependencies]
+-baz = "1.2"
++baz = "1"
+-qux = { version = "0.2", optional = true }
++qux = { version = ">= 0.1, < 0.3", optional = true }
+ 
+ [target.'cfg(unix)'.dependencies.libc]
+-version = "0.2.170"
++version = "0.2.160"
+--- foo.orig/src/lib.rs
++++ foo/src/lib.rs
+@@ -1,1 +1,1 @@
+-version = "9"
++version = "10"
+"#,
+        );
+        let req = |name: &str| &requirements[&(DependencyKind::Normal, name.to_string())];
+        let parse = |req| PatchedDependency::Requirement(VersionReq::parse(req).unwrap());
+        assert_eq!(requirements.len(), 4);
+        assert_eq!(*req("bar"), parse(">= 0.3, < 0.5"));
+        assert_eq!(*req("baz"), parse("1"));
+        assert_eq!(*req("qux"), parse(">= 0.1, < 0.3"));
+        assert_eq!(*req("libc"), parse("0.2.160"));
+    }
+
+    #[test]
+    fn parse_renamed_and_removed_dependencies() {
+        let requirements = parse_patch(
+            r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -20,12 +20,9 @@
+ [dependencies.rand_old]
+ package = "rand"
+-version = "0.7"
++version = "0.8"
+ 
+-[dependencies.winapi]
+-version = "0.3"
+-
+ [dev-dependencies]
+-criterion = "0.3"
+-proptest = "1.0"
++proptest = "1"
++getrandom_old = { package = "getrandom", version = "0.2" }
+ 
+ [build-dependencies.rand_old]
+ package = "rand"
+"#,
+        );
+        let key = |kind, name: &str| (kind, name.to_string());
+        let req = |req| PatchedDependency::Requirement(VersionReq::parse(req).unwrap());
+        assert_eq!(
+            requirements,
+            HashMap::from([
+                (key(DependencyKind::Normal, "rand"), req("0.8")),
+                (
+                    key(DependencyKind::Normal, "winapi"),
+                    PatchedDependency::Removed
+                ),
+                (
+                    key(DependencyKind::Development, "criterion"),
+                    PatchedDependency::Removed
+                ),
+                (key(DependencyKind::Development, "proptest"), req("1")),
+                (key(DependencyKind::Development, "getrandom"), req("0.2")),
+            ])
+        );
+    }
+}
@@ -1,14 +1,30 @@
-use crate::args::Args;
-use crate::db::{Client, Connection, PkgStatus};
+use crate::config::{Annotation, AnnotationKind, CrateConfig};
+use crate::db::{
+    Archive, Candidate, Connection, Distribution, NameOverride, PkgStatus, Recommendation,
+    TestingRisk, semver_bucket,
+};
+use crate::debcargo::{self, PatchedDependency, Preparation};
+use crate::debversion::DebVersion;
 use crate::errors::*;
 use crate::graph::Graph;
+use crate::pool::{self, Pool, RETRIES};
+use crate::wnpp::WnppBug;
 use cargo_metadata::{Package, PackageId, Source};
+use clap::ValueEnum;
+use crossbeam_channel::RecvTimeoutError;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
-use log::error;
-use semver::Version;
-use std::path::PathBuf;
+use log::warn;
+use petgraph::EdgeDirection;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Pkg {
@@ -20,23 +36,65 @@ pub struct Pkg {
     pub manifest_path: PathBuf,
     pub license: Option<String>,
     pub repository: Option<String>,
+    /// Features enabled in the resolved dependency graph
+    pub features: Vec<String>,
+    /// Number of packages depending on this one
+    pub dependents: usize,
+    /// Version requirements of the packages depending on this one, including
+    /// the ones hidden by `--edges`, as in [`Graph::lockfile_requirements`]
+    pub requirements: Vec<VersionReq>,
+    /// Version requirements of the dependents after applying their Debian
+    /// patches, only set if a patch changes any of them
+    pub patched_requirements: Vec<VersionReq>,
+    /// Set if this crate is intentionally not packaged
+    pub annotation: Option<Annotation>,
+    /// Why the Debian status of this crate couldn't be determined
+    pub lookup_error: Option<String>,
 
     pub debinfo: Option<DebianInfo>,
 }
 
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
 pub enum PackagingProgress {
     Available,
     AvailableInNew,
+    /// being packaged in debcargo-conf, but not uploaded yet
+    InPreparation,
     /// debian has an older version of this dependency, which needs to be updated
     NeedsUpdate,
     /// debian has a newer version of this dependency, so we need to patch its dependent
     NeedsPatching,
     Missing,
     MissingInWorkspace,
+    /// the debian status couldn't be determined, e.g. the database wasn't reachable
+    LookupFailed,
+    /// not needed in debian, e.g. vendored or only used for development
+    Ignored,
+    /// removed from the dependencies with a patch in debian
+    PatchedOut,
 }
 
 use std::fmt;
 
+impl PackagingProgress {
+    /// Name of the status, as used by `--status`
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            PackagingProgress::Available => "available",
+            PackagingProgress::AvailableInNew => "available-in-new",
+            PackagingProgress::InPreparation => "in-preparation",
+            PackagingProgress::NeedsUpdate => "needs-update",
+            PackagingProgress::NeedsPatching => "needs-patching",
+            PackagingProgress::Missing => "missing",
+            PackagingProgress::MissingInWorkspace => "missing-in-workspace",
+            PackagingProgress::LookupFailed => "lookup-failed",
+            PackagingProgress::Ignored => "ignored",
+            PackagingProgress::PatchedOut => "patched-out",
+        }
+    }
+}
+
 impl fmt::Display for PackagingProgress {
     //! Generate icons to display the packaging progress.
     //! They should all take the same width when printed in a terminal
@@ -44,10 +102,14 @@ impl fmt::Display for PackagingProgress {
         let icon = match self {
             PackagingProgress::Available => "  ",
             PackagingProgress::AvailableInNew => "✨",
+            PackagingProgress::InPreparation => "🚧",
             PackagingProgress::NeedsUpdate => "⌛",
             PackagingProgress::NeedsPatching => "🔽",
             PackagingProgress::Missing => "🔴",
             PackagingProgress::MissingInWorkspace => "🪏 ",
+            PackagingProgress::LookupFailed => "❓",
+            PackagingProgress::Ignored => "💤",
+            PackagingProgress::PatchedOut => "🩹",
         };
         write!(f, "{}", icon)
     }
@@ -64,6 +126,12 @@ impl Pkg {
             manifest_path: pkg.manifest_path.into(),
             license: pkg.license,
             repository: pkg.repository,
+            features: Vec::new(),
+            dependents: 0,
+            requirements: Vec::new(),
+            patched_requirements: Vec::new(),
+            annotation: None,
+            lookup_error: None,
 
             debinfo: None,
         }
@@ -71,13 +139,18 @@ impl Pkg {
 
     pub fn in_debian(&self) -> bool {
         if let Some(deb) = &self.debinfo {
-            deb.in_unstable || deb.in_new
+            deb.in_release || deb.in_new
         } else {
             false
         }
     }
 
     pub fn show_dependencies(&self) -> bool {
+        // the dependencies of crates we don't package don't matter either
+        if self.annotation.is_some() {
+            return false;
+        }
+
         if !self.in_debian() {
             return true;
         }
@@ -90,8 +163,15 @@ impl Pkg {
     }
 
     pub fn packaging_status(&self) -> PackagingProgress {
-        if let Some(deb) = &self.debinfo {
-            if deb.in_unstable {
+        if let Some(annotation) = &self.annotation {
+            match annotation.kind {
+                AnnotationKind::Ignored => PackagingProgress::Ignored,
+                AnnotationKind::PatchedOut => PackagingProgress::PatchedOut,
+            }
+        } else if self.debinfo.is_none() && self.lookup_error.is_some() {
+            PackagingProgress::LookupFailed
+        } else if let Some(deb) = &self.debinfo {
+            if deb.in_release {
                 if deb.compatible {
                     // Available at an older yet compatible version
                     PackagingProgress::Available
@@ -117,6 +197,8 @@ impl Pkg {
                 PackagingProgress::NeedsUpdate
             } else if deb.newer {
                 PackagingProgress::NeedsPatching
+            } else if deb.debcargo_conf.is_some() {
+                PackagingProgress::InPreparation
             } else if self.workspace_member {
                 PackagingProgress::MissingInWorkspace
             } else {
@@ -128,40 +210,245 @@ impl Pkg {
             PackagingProgress::Missing
         }
     }
+
+    /// Describe what needs to be done in Debian, e.g. `add rust-foo-0.3`
+    pub fn recommended_action(&self) -> Option<String> {
+        let deb = self.debinfo.as_ref()?;
+        let action = match deb.recommendation? {
+            Recommendation::UpdateMain => {
+                let source = deb
+                    .package
+                    .as_ref()
+                    .and_then(|package| package.source.as_deref())
+                    .unwrap_or(&deb.source);
+                format!("update {source} to {}", self.version)
+            }
+            Recommendation::AddCompatPackage => {
+                format!("add {}-{}", deb.source, semver_bucket(&self.version))
+            }
+            Recommendation::PatchDependent => format!("patch dependents to {}", deb.version),
+        };
+        Some(action)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DebianInfo {
-    pub in_unstable: bool,
+    /// The archive the crate was looked up in
+    pub distribution: Distribution,
+    /// Release of the distribution, e.g. `sid` or `noble`
+    pub series: String,
+    /// Set if the crate is in the release that was looked up
+    pub in_release: bool,
     pub in_new: bool,
     pub outdated: bool,
     pub newer: bool,
     pub compatible: bool,
     pub exact_match: bool,
     pub version: String,
+    /// Debian package the crate was found in, if it isn't named after the crate
+    pub packaged_as: Option<String>,
+    /// The source package shipping the crate, or the one debcargo would
+    /// create for it, e.g. `rust-resvg` for usvg
+    pub source: String,
+    /// The Debian package `version` was taken from
+    pub package: Option<Candidate>,
+    /// All packages found in Debian, most recent first
+    pub candidates: Vec<Candidate>,
+    pub recommendation: Option<Recommendation>,
+    /// The additional APT index that satisfied this crate, if not the database
+    pub origin: Option<String>,
+    /// Set if Debian only matches the requirements of the dependents, not the
+    /// version in Cargo.lock
+    pub lockfile_stricter: bool,
+    /// Set if Debian only matches the requirements of the dependents after
+    /// the patches in debcargo-conf are applied to them
+    pub satisfied_by_patch: bool,
+    /// An ITP, RFP or orphaning bug if the crate isn't packaged
+    pub wnpp: Option<WnppBug>,
+    /// Set with `--testing` if the package in unstable might not reach testing
+    pub testing: Option<TestingRisk>,
+    /// Packaging in a debcargo-conf checkout, if the crate isn't in Debian yet
+    pub debcargo_conf: Option<Preparation>,
+}
+
+impl DebianInfo {
+    pub fn at_risk(&self) -> bool {
+        self.testing.as_ref().is_some_and(|risk| risk.at_risk())
+    }
+
+    /// Set if the crate was looked up in Debian unstable, the default
+    pub fn is_unstable(&self) -> bool {
+        self.distribution == Distribution::Debian && self.series == "sid"
+    }
+
+    /// Set if the crate was found in the release that was looked up, rather
+    /// than in an additional APT index
+    pub fn in_archive(&self) -> bool {
+        self.in_release && self.origin.is_none()
+    }
+
+    /// Where the crate was found, e.g. `debian` for unstable, `ubuntu noble` or
+    /// the APT index it was taken from
+    pub fn release_label(&self) -> String {
+        if let Some(origin) = &self.origin {
+            origin.clone()
+        } else if self.is_unstable() {
+            self.distribution.to_string()
+        } else {
+            format!("{} {}", self.distribution, self.series)
+        }
+    }
+
+    /// Where a crate in the NEW queue was found, the queue only feeds Debian unstable
+    pub fn new_queue_label(&self) -> String {
+        if self.is_unstable() {
+            "debian NEW queue".to_string()
+        } else {
+            format!("debian NEW queue, not in {}", self.release_label())
+        }
+    }
+}
+
+/// How crates are looked up, e.g. from the command line with
+/// [`Args::lookup_options`](crate::Args::lookup_options)
+#[derive(Debug, Clone)]
+pub struct LookupOptions {
+    /// Do not read from disk cache for Debian database results
+    pub skip_cache: bool,
+    /// Check packaged crates for RC bugs and testing migration blockers
+    pub testing: bool,
+    /// Check a debcargo-conf checkout for crates that are being packaged
+    pub debcargo_conf: Option<PathBuf>,
+    /// How many connections to use concurrently
+    pub concurrency: usize,
+    /// How often to retry a lookup after a connection error or an overloaded database
+    pub retries: u32,
+    /// Don't show a progress bar
+    pub quiet: bool,
+    /// Per-crate overrides, e.g. the Debian package names of a crate
+    pub crates: BTreeMap<String, CrateConfig>,
+}
+
+impl Default for LookupOptions {
+    fn default() -> Self {
+        LookupOptions {
+            skip_cache: false,
+            testing: false,
+            debcargo_conf: None,
+            concurrency: 24,
+            retries: RETRIES,
+            quiet: false,
+            crates: BTreeMap::new(),
+        }
+    }
+}
+
+/// Debian package names configured for a crate, falling back to the built-in list
+fn name_override(options: &LookupOptions, name: &str) -> Option<NameOverride> {
+    options
+        .crates
+        .get(name)
+        .and_then(|config| config.name_override())
+        .or_else(|| NameOverride::builtin(name))
+}
+
+// How often the progress bar checks for an interruption
+const TICK: Duration = Duration::from_millis(100);
+
+// Sent by the workers while looking up crates
+enum Progress {
+    Started(String),
+    Finished {
+        idx: NodeIndex,
+        result: Result<Box<DebianInfo>>,
+        /// Whether results from the disk cache were used
+        cached: bool,
+    },
+}
+
+/// Look up all versions of a crate with the same connection, retrying them
+/// together if the connection breaks
+fn run_group<C: Archive>(
+    pool: &Pool<C>,
+    group: &[(NodeIndex, Pkg)],
+    options: &LookupOptions,
+) -> Vec<Progress> {
+    let results = pool.run(|db| {
+        let mut results = Vec::new();
+        for (idx, pkg) in group {
+            let cache_hits = db.cache_hits();
+            match run_task(db, pkg, options) {
+                Err(err) if pool::is_transient(&err) => return Err(err),
+                result => results.push(Progress::Finished {
+                    idx: *idx,
+                    result: result.map(Box::new),
+                    cached: db.cache_hits() > cache_hits,
+                }),
+            }
+        }
+        Ok(results)
+    });
+    results.unwrap_or_else(|err| {
+        group
+            .iter()
+            .map(|(idx, _)| Progress::Finished {
+                idx: *idx,
+                result: Err(anyhow!("{err:#}")),
+                cached: false,
+            })
+            .collect()
+    })
 }
 
-fn run_task<C: Client>(db: &mut Connection<C>, pkg: Pkg, skip_cache: bool) -> Result<DebianInfo> {
+fn run_task<C: Archive>(
+    db: &mut Connection<C>,
+    pkg: &Pkg,
+    options: &LookupOptions,
+) -> Result<DebianInfo> {
     let mut deb = DebianInfo {
-        in_unstable: false,
+        distribution: db.distribution(),
+        series: db.series().to_string(),
+        in_release: false,
         in_new: false,
         outdated: false,
         newer: false,
         compatible: false,
         exact_match: false,
         version: String::new(),
+        packaged_as: None,
+        source: String::new(),
+        package: None,
+        candidates: Vec::new(),
+        recommendation: None,
+        origin: None,
+        lockfile_stricter: false,
+        satisfied_by_patch: false,
+        wnpp: None,
+        testing: None,
+        debcargo_conf: None,
     };
 
-    let mut info = db.search(&pkg.name, &pkg.version, skip_cache)?;
+    let names = name_override(options, &pkg.name);
+    let mut info = db.search(&pkg.name, &pkg.version, names.as_ref(), options.skip_cache)?;
     if info.status == PkgStatus::NotFound {
-        info = db.search_new(&pkg.name, &pkg.version, skip_cache)?;
+        info = db.search_new(&pkg.name, &pkg.version, names.as_ref(), options.skip_cache)?;
         if info.status != PkgStatus::NotFound {
             deb.in_new = true;
             deb.version = info.version;
+            deb.packaged_as = info.packaged_as;
+            deb.package = info.package;
+            deb.candidates = info.candidates;
+            deb.recommendation = info.recommendation;
         }
     } else {
-        deb.in_unstable = true;
+        deb.in_release = true;
         deb.version = info.version;
+        deb.packaged_as = info.packaged_as;
+        deb.package = info.package;
+        deb.candidates = info.candidates;
+        deb.recommendation = info.recommendation;
+        deb.origin = info.origin;
     }
 
     match info.status {
@@ -172,82 +459,675 @@ fn run_task<C: Client>(db: &mut Connection<C>, pkg: Pkg, skip_cache: bool) -> Re
         _ => (),
     }
 
+    if !deb.exact_match {
+        match_requirements(&mut deb, pkg);
+    }
+    deb.source = debian_source(&deb, names.as_ref(), &pkg.name);
+
+    // testing migration only exists for debian unstable
+    if options.testing && deb.in_archive() && deb.is_unstable() {
+        let source = deb
+            .package
+            .as_ref()
+            .and_then(|package| package.source.clone())
+            .unwrap_or_else(|| deb.source.clone());
+        // the lookup in unstable succeeded, so don't throw it away
+        match db.search_testing(&source, &pkg.version, options.skip_cache) {
+            Ok(risk) => deb.testing = Some(risk),
+            Err(err) => warn!(
+                "Failed to check testing migration for {}: {err:#}",
+                pkg.name
+            ),
+        }
+    }
+
+    if info.status == PkgStatus::NotFound
+        && let Some(checkout) = &options.debcargo_conf
+    {
+        match debcargo::find(checkout, &pkg.name, &pkg.version) {
+            Ok(prep) => deb.debcargo_conf = prep,
+            Err(err) => warn!("Failed to check debcargo-conf for {}: {err:#}", pkg.name),
+        }
+    }
+
+    if info.status == PkgStatus::NotFound {
+        // someone may already be working on it, but that's no reason to fail the lookup
+        match db.search_wnpp(&pkg.name, &pkg.version, names.as_ref(), options.skip_cache) {
+            Ok(bug) => deb.wnpp = bug,
+            Err(err) => warn!("Failed to look up wnpp bugs for {}: {err:#}", pkg.name),
+        }
+    }
+
     Ok(deb)
 }
 
-pub fn populate<C: Client>(
+// The source package of a crate: the configured one, the one shipping the
+// configured binary package, or the one debcargo would name after the crate
+fn debian_source(deb: &DebianInfo, names: Option<&NameOverride>, name: &str) -> String {
+    if let Some(source) = names.and_then(|names| names.source.as_ref()) {
+        return source.clone();
+    }
+    let binary = names.and_then(|names| names.binary.as_ref());
+    if let Some(package) = &deb.package
+        && binary.is_some()
+        && package.binary.as_ref() == binary
+        && let Some(source) = &package.source
+    {
+        return source.clone();
+    }
+    format!("rust-{}", name.replace('_', "-").to_lowercase())
+}
+
+// Check if a Debian version would do for all dependents, even if it doesn't
+// match the version in Cargo.lock, first as they are and then as patched in Debian
+fn match_requirements(deb: &mut DebianInfo, pkg: &Pkg) {
+    // path dependencies are always used at the version in the workspace
+    if pkg.source.is_none() {
+        return;
+    }
+
+    let find_matching = |requirements: &[VersionReq]| {
+        if requirements.is_empty() {
+            return None;
+        }
+        deb.candidates.iter().find_map(|candidate| {
+            let debversion = candidate.version.parse::<DebVersion>().ok()?;
+            let version = debversion.to_semver().ok()?;
+            requirements
+                .iter()
+                .all(|req| req.matches(&version))
+                .then(|| (debversion.upstream, candidate.clone()))
+        })
+    };
+
+    let (matching, patched) = match find_matching(&pkg.requirements) {
+        Some(matching) => (matching, false),
+        None => match find_matching(&pkg.patched_requirements) {
+            Some(matching) => (matching, true),
+            None => return,
+        },
+    };
+
+    let (version, candidate) = matching;
+    deb.version = version;
+    deb.package = Some(candidate);
+    deb.exact_match = true;
+    deb.compatible = false;
+    deb.outdated = false;
+    deb.newer = false;
+    deb.recommendation = None;
+    deb.lockfile_stricter = !patched;
+    deb.satisfied_by_patch = patched;
+}
+
+// Apply the dependency changes of the patches in debcargo-conf to the
+// requirements of every dependent packaged there
+fn patch_requirements(graph: &mut Graph, checkout: &Path) {
+    let mut patches = HashMap::new();
+    let mut patched = vec![];
+
+    for idx in graph.graph.node_indices() {
+        let name = &graph.graph[idx].name;
+        let mut requirements = vec![];
+        let mut changed = false;
+
+        for edge in graph.graph.edges_directed(idx, EdgeDirection::Incoming) {
+            let dependent = &graph.graph[edge.source()];
+            // only crates from a registry can be packaged, workspace and path
+            // crates aren't patched by debian even if a directory is named alike
+            let from_registry = dependent.source.as_ref().is_some_and(|source| {
+                source.repr.starts_with("registry+") || source.repr.starts_with("sparse+")
+            });
+            let change = from_registry
+                .then(|| {
+                    patches.entry(edge.source()).or_insert_with(|| {
+                        debcargo::patched_requirements(
+                            checkout,
+                            &dependent.name,
+                            &dependent.version,
+                        )
+                        .unwrap_or_else(|err| {
+                            warn!(
+                                "Failed to read debian patches of {}: {err:#}",
+                                dependent.name
+                            );
+                            HashMap::new()
+                        })
+                    })
+                })
+                .and_then(|changes| changes.get(&(edge.weight().kind, name.clone())));
+
+            let req = match change {
+                Some(PatchedDependency::Requirement(req)) if *req != edge.weight().req => {
+                    changed = true;
+                    req.clone()
+                }
+                // the dependent doesn't need this crate in debian at all
+                Some(PatchedDependency::Removed) => {
+                    changed = true;
+                    continue;
+                }
+                _ => edge.weight().req.clone(),
+            };
+            if !requirements.contains(&req) {
+                requirements.push(req);
+            }
+        }
+
+        if changed {
+            patched.push((idx, requirements));
+        }
+    }
+
+    for (idx, requirements) in patched {
+        graph.graph[idx].patched_requirements = requirements;
+    }
+}
+
+pub fn populate<C: Archive + Send>(
+    graph: &mut Graph,
+    options: &LookupOptions,
+    new_connection: &(impl Fn() -> Result<Connection<C>, Error> + Sync),
+) -> Result<(), Error> {
+    populate_until(graph, options, new_connection, &AtomicBool::new(false))
+}
+
+/// Like [`populate`], but stops looking up crates once `interrupted` is set,
+/// e.g. by a Ctrl-C handler. The crates that were looked up by then keep their
+/// results, the others are marked as interrupted.
+pub fn populate_until<C: Archive + Send>(
     graph: &mut Graph,
-    args: &Args,
-    new_connection: &'static (impl Fn() -> Result<Connection<C>, Error> + Send + Sync),
+    options: &LookupOptions,
+    new_connection: &(impl Fn() -> Result<Connection<C>, Error> + Sync),
+    interrupted: &AtomicBool,
 ) -> Result<(), Error> {
-    let (task_tx, task_rx) = crossbeam_channel::unbounded();
-    let (return_tx, return_rx) = crossbeam_channel::unbounded();
-
-    info!("Creating thread-pool");
-    for _ in 0..args.concurrency {
-        let args = args.clone();
-        let task_rx = task_rx.clone();
-        let return_tx = return_tx.clone();
-
-        thread::spawn(move || {
-            let mut db = match new_connection() {
-                Ok(db) => db,
-                Err(err) => {
-                    return_tx.send(Err(err)).unwrap();
-                    return;
+    if let Some(checkout) = &options.debcargo_conf {
+        info!("Reading debian patches");
+        patch_requirements(graph, checkout);
+    }
+
+    // connections are opened on demand, a failing one only affects the crates
+    // that were looked up with it
+    let pool = Pool::new(new_connection).with_retries(options.retries);
+
+    thread::scope(|scope| {
+        let (task_tx, task_rx) = crossbeam_channel::unbounded::<Vec<(NodeIndex, Pkg)>>();
+        let (return_tx, return_rx) = crossbeam_channel::unbounded();
+
+        info!("Creating thread-pool");
+        for _ in 0..options.concurrency {
+            let task_rx = task_rx.clone();
+            let return_tx = return_tx.clone();
+            let pool = &pool;
+
+            scope.spawn(move || {
+                for group in task_rx {
+                    if return_tx
+                        .send(Progress::Started(group[0].1.name.clone()))
+                        .is_err()
+                    {
+                        // nobody is waiting for the results anymore
+                        return;
+                    }
+                    for progress in run_group(pool, &group, options) {
+                        if return_tx.send(progress).is_err() {
+                            return;
+                        }
+                    }
                 }
+            });
+        }
+        drop(return_tx);
+
+        info!("Getting node indices");
+        // crates we don't intend to package don't need to be looked up
+        let idxs = graph
+            .graph
+            .node_indices()
+            .filter(|idx| graph.graph[*idx].annotation.is_none())
+            .collect::<Vec<_>>();
+        let jobs = idxs.len();
+        debug!("Found node indices: {}", jobs);
+
+        // all versions of a crate are looked up together, so their candidates
+        // are only fetched once, in alphabetical order
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for idx in &idxs {
+            if let Some(pkg) = graph.graph.node_weight(*idx) {
+                debug!("Adding job for {:?}: {:?}", idx, pkg);
+                groups
+                    .entry(pkg.name.clone())
+                    .or_default()
+                    .push((*idx, pkg.clone()));
+            }
+        }
+        for group in groups.into_values() {
+            task_tx.send(group).unwrap();
+        }
+        // workers stop once the queue is drained
+        drop(task_tx);
+
+        info!("Processing debian results");
+
+        let pb = if options.quiet {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(jobs as u64)
+                .with_style(
+                    ProgressStyle::default_bar()
+                        .template("[{pos:.green}/{len:.green}] {prefix:.bold} {wide_bar} {msg}")?,
+                )
+                .with_prefix("Resolving debian packages")
+        };
+        pb.tick();
+
+        let mut current = String::new();
+        let (mut done, mut failed, mut cache_hits) = (0, 0, 0);
+        while done < jobs {
+            if interrupted.load(Ordering::SeqCst) {
+                warn!("Interrupted, stopping the remaining lookups");
+                pool.cancel();
+                break;
+            }
+            let progress = match return_rx.recv_timeout(TICK) {
+                Ok(progress) => progress,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
             };
 
-            for (idx, pkg) in task_rx {
-                let deb = run_task(&mut db, pkg, args.skip_cache);
-                if let Err(err) = return_tx.send(Ok((idx, deb))) {
-                    error!("could not send task result: {err}");
-                    break;
+            match progress {
+                Progress::Started(name) => current = name,
+                Progress::Finished {
+                    idx,
+                    result,
+                    cached,
+                } => {
+                    if let Some(pkg) = graph.graph.node_weight_mut(idx) {
+                        // a single crate failing shouldn't prevent us from reporting the others
+                        match result {
+                            Ok(deb) => pkg.debinfo = Some(*deb),
+                            Err(err) => {
+                                warn!("Failed to look up {} v{}: {err:#}", pkg.name, pkg.version);
+                                pkg.lookup_error = Some(format!("{err:#}"));
+                                failed += 1;
+                            }
+                        }
+                    }
+                    cache_hits += cached as usize;
+                    done += 1;
+                    pb.inc(1);
                 }
             }
-        });
-    }
+            pb.set_message(format!("{current} ({failed} failed, {cache_hits} cached)"));
+        }
 
-    info!("Getting node indices");
-    let idxs = graph.graph.node_indices().collect::<Vec<_>>();
-    let jobs = idxs.len();
-    debug!("Found node indices: {}", jobs);
+        pb.finish_and_clear();
 
-    for idx in idxs {
-        if let Some(pkg) = graph.graph.node_weight_mut(idx) {
-            debug!("Adding job for {:?}: {:?}", idx, pkg);
-            let pkg = pkg.clone();
-            task_tx.send((idx, pkg)).unwrap();
+        // keep the lookups that finished while the others were being cancelled,
+        // the ones failing now most likely failed because of the cancellation
+        for progress in return_rx {
+            if let Progress::Finished {
+                idx,
+                result: Ok(deb),
+                ..
+            } = progress
+            {
+                graph.graph[idx].debinfo = Some(*deb);
+            }
+        }
+
+        for idx in idxs {
+            let pkg = &mut graph.graph[idx];
+            if pkg.debinfo.is_none() && pkg.lookup_error.is_none() {
+                pkg.lookup_error = Some("interrupted".to_string());
+            }
+        }
+
+        Ok::<_, Error>(())
+    })?;
+
+    recommend_patching(graph, options);
+    Ok(())
+}
+
+// Porting the dependents to the version in Debian is only an option if they
+// are packaged there too, or if it's configured for the crate
+fn recommend_patching(graph: &mut Graph, options: &LookupOptions) {
+    let mut patch = vec![];
+    for idx in graph.graph.node_indices() {
+        let pkg = &graph.graph[idx];
+        let Some(deb) = &pkg.debinfo else {
+            continue;
+        };
+        if deb.recommendation != Some(Recommendation::AddCompatPackage) {
+            continue;
+        }
+
+        let opt_in = options
+            .crates
+            .get(&pkg.name)
+            .is_some_and(|config| config.patch_dependents);
+        let mut dependents = graph
+            .graph
+            .neighbors_directed(idx, EdgeDirection::Incoming)
+            .peekable();
+        let dependents_in_debian =
+            dependents.peek().is_some() && dependents.all(|dep| graph.graph[dep].in_debian());
+        if opt_in || dependents_in_debian {
+            patch.push(idx);
         }
     }
 
-    info!("Processing debian results");
+    for idx in patch {
+        if let Some(deb) = &mut graph.graph[idx].debinfo {
+            deb.recommendation = Some(Recommendation::PatchDependent);
+        }
+    }
+}
 
-    let pb = if args.quiet {
-        ProgressBar::hidden()
-    } else {
-        ProgressBar::new(jobs as u64)
-            .with_style(
-                ProgressStyle::default_bar()
-                    .template("[{pos:.green}/{len:.green}] {prefix:.bold} {wide_bar}")?,
-            )
-            .with_prefix("Resolving debian packages")
+#[cfg(test)]
+mod tests {
+    use super::{LookupOptions, PackagingProgress, Pkg, populate};
+    use crate::apt::AptIndex;
+    use crate::config::CrateConfig;
+    use crate::db::Distribution;
+    use crate::debcargo::Preparation;
+    use crate::graph::{self, Graph, GraphOptions};
+    use crate::tree::tests::{
+        MockedResponse, flat_metadata, hidden_dev_dependency, leak_mock_connection, mock_responses,
+        registry_metadata,
     };
-    pb.tick();
+    use anyhow::Error;
+    use cargo_metadata::DependencyKind;
+    use std::fs;
 
-    for result in return_rx.iter().take(jobs) {
-        let result = result.context("A worker crashed")?;
+    fn flat_graph() -> Result<Graph, Error> {
+        graph::build(&GraphOptions::default(), flat_metadata()?)
+    }
 
-        let idx = result.0;
-        let deb = result.1?;
+    fn lookup(
+        mut graph: Graph,
+        options: &LookupOptions,
+        responses: impl Fn() -> Vec<MockedResponse> + Sync,
+    ) -> Result<Graph, Error> {
+        populate(&mut graph, options, &|| {
+            Ok(leak_mock_connection(responses()))
+        })?;
+        Ok(graph)
+    }
 
-        if let Some(pkg) = graph.graph.node_weight_mut(idx) {
-            pkg.debinfo = Some(deb);
-        }
-        pb.inc(1);
+    fn find<'a>(graph: &'a Graph, name: &str) -> &'a Pkg {
+        graph
+            .graph
+            .node_weights()
+            .find(|pkg| pkg.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn recommend_packages_named_after_the_override() -> Result<(), Error> {
+        // b is shipped by rust-bee, which only has 2.1.0 in debian
+        let responses = || {
+            let mut responses = mock_responses();
+            for (query, params, rows) in &mut responses {
+                if query.contains("FROM packages") && params[0] == "librust-b-dev" {
+                    *params = vec!["librust-bee-dev", "^librust-bee-[0-9.]+-dev$", "sid"];
+                    *rows = vec![vec![
+                        "2.1.0-1",
+                        "rust-bee",
+                        "librust-bee-dev",
+                        "sid",
+                        "main",
+                    ]];
+                }
+            }
+            responses
+        };
+
+        let configured = |config: CrateConfig| LookupOptions {
+            quiet: true,
+            crates: [("b".to_string(), config)].into(),
+            ..LookupOptions::default()
+        };
+        let graph = lookup(
+            flat_graph()?,
+            &configured(CrateConfig {
+                debian_source: Some("rust-bee".to_string()),
+                debian_binary: Some("librust-bee-dev".to_string()),
+                ..Default::default()
+            }),
+            responses,
+        )?;
+        assert_eq!(
+            find(&graph, "b").recommended_action().as_deref(),
+            Some("add rust-bee-1")
+        );
+
+        // the source of the configured binary package is used as well
+        let graph = lookup(
+            flat_graph()?,
+            &configured(CrateConfig {
+                debian_binary: Some("librust-bee-dev".to_string()),
+                ..Default::default()
+            }),
+            responses,
+        )?;
+        assert_eq!(
+            find(&graph, "b").recommended_action().as_deref(),
+            Some("add rust-bee-1")
+        );
+        Ok(())
     }
 
-    pb.finish_and_clear();
+    #[test]
+    fn match_requirements_of_hidden_dependents() -> Result<(), Error> {
+        let options = LookupOptions {
+            quiet: true,
+            ..LookupOptions::default()
+        };
+        let metadata = hidden_dev_dependency(registry_metadata(&[("c", ">=0.4")])?)?;
 
-    Ok(())
+        // c 0.4.5 would do for the workspace, but not for the dev-dependency of d
+        let graph_options = GraphOptions {
+            dependency_kinds: vec![DependencyKind::Normal, DependencyKind::Build],
+            ..GraphOptions::default()
+        };
+        let graph = graph::build(&graph_options, metadata.clone())?;
+        let graph = lookup(graph, &options, mock_responses)?;
+        let c = find(&graph, "c");
+        assert_eq!(c.packaging_status(), PackagingProgress::NeedsUpdate);
+        assert!(!c.debinfo.as_ref().unwrap().lockfile_stricter);
+
+        // no matter if it's displayed
+        let graph = graph::build(&GraphOptions::default(), metadata)?;
+        let graph = lookup(graph, &options, mock_responses)?;
+        let c = find(&graph, "c");
+        assert_eq!(c.packaging_status(), PackagingProgress::NeedsUpdate);
+        Ok(())
+    }
+
+    #[test]
+    fn keep_lookups_when_testing_check_fails() -> Result<(), Error> {
+        let options = LookupOptions {
+            testing: true,
+            quiet: true,
+            ..LookupOptions::default()
+        };
+        let responses = || {
+            mock_responses()
+                .into_iter()
+                .filter(|(query, _, _)| !query.contains("migration_excuses"))
+                .collect()
+        };
+        let graph = lookup(flat_graph()?, &options, responses)?;
+
+        let a = find(&graph, "a");
+        assert_eq!(a.packaging_status(), PackagingProgress::Available);
+        assert_eq!(a.lookup_error, None);
+        assert!(a.debinfo.as_ref().unwrap().testing.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn check_testing_migration_of_packaged_crates() -> Result<(), Error> {
+        let options = LookupOptions {
+            testing: true,
+            quiet: true,
+            ..LookupOptions::default()
+        };
+        let graph = lookup(flat_graph()?, &options, mock_responses)?;
+
+        let testing = |name| find(&graph, name).debinfo.as_ref().unwrap().testing.clone();
+        let a = testing("a").unwrap();
+        assert!(a.at_risk());
+        assert_eq!(a.rc_bugs[0].id, 1000002);
+        assert_eq!(a.migration_blocker.as_deref(), Some("REJECTED_PERMANENTLY"));
+        assert!(!testing("b").unwrap().at_risk());
+        // crates that aren't packaged can't migrate
+        assert!(testing("d").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn find_missing_crates_in_debcargo_conf() -> Result<(), Error> {
+        let checkout = tempfile::tempdir()?;
+        let changelog = |dir: &str, entry: &str| -> Result<(), Error> {
+            let debian = checkout.path().join("src").join(dir).join("debian");
+            fs::create_dir_all(&debian)?;
+            fs::write(debian.join("changelog"), entry)?;
+            Ok(())
+        };
+        changelog("d", "rust-d (1.0.0-1) UNRELEASED; urgency=medium\n")?;
+        // only checked for crates that aren't in debian
+        changelog("a", "rust-a (1.0.0-2) unstable; urgency=medium\n")?;
+
+        let options = LookupOptions {
+            debcargo_conf: Some(checkout.path().to_path_buf()),
+            quiet: true,
+            ..LookupOptions::default()
+        };
+        let graph = lookup(flat_graph()?, &options, mock_responses)?;
+
+        let d = find(&graph, "d");
+        assert_eq!(d.packaging_status(), PackagingProgress::InPreparation);
+        assert_eq!(
+            d.debinfo.as_ref().unwrap().debcargo_conf,
+            Some(Preparation {
+                dir: "d".to_string(),
+                version: "1.0.0-1".to_string(),
+                distribution: "UNRELEASED".to_string(),
+            })
+        );
+        let a = find(&graph, "a");
+        assert_eq!(a.packaging_status(), PackagingProgress::Available);
+        assert_eq!(a.debinfo.as_ref().unwrap().debcargo_conf, None);
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_incompatible_packaging_in_debcargo_conf() -> Result<(), Error> {
+        let checkout = tempfile::tempdir()?;
+        let debian = checkout.path().join("src/d/debian");
+        fs::create_dir_all(&debian)?;
+        fs::write(
+            debian.join("changelog"),
+            "rust-d (2.0.0-1) UNRELEASED; urgency=medium\n",
+        )?;
+
+        let options = LookupOptions {
+            debcargo_conf: Some(checkout.path().to_path_buf()),
+            quiet: true,
+            ..LookupOptions::default()
+        };
+        let graph = lookup(flat_graph()?, &options, mock_responses)?;
+
+        let d = find(&graph, "d");
+        assert_eq!(d.packaging_status(), PackagingProgress::MissingInWorkspace);
+        assert_eq!(d.debinfo.as_ref().unwrap().debcargo_conf, None);
+        Ok(())
+    }
+
+    #[test]
+    fn label_crates_with_the_release() -> Result<(), Error> {
+        let responses = || {
+            let mut responses = mock_responses();
+            for (query, params, rows) in &mut responses {
+                let rename = |value: &mut &str| {
+                    if *value == "sid" {
+                        *value = "trixie";
+                    }
+                };
+                params.iter_mut().for_each(rename);
+                rows.iter_mut().flatten().for_each(rename);
+                if query.contains("FROM new_sources") && params[0] == "rust-d" {
+                    *rows = vec![vec!["1.0.0-1", "rust-d", "", "new", "main"]];
+                }
+            }
+            responses
+        };
+        let mut graph = flat_graph()?;
+        populate(&mut graph, &LookupOptions::default(), &|| {
+            leak_mock_connection(responses())
+                .with_distribution(Distribution::Debian, Some("trixie"))
+        })?;
+
+        let a = find(&graph, "a").debinfo.as_ref().unwrap();
+        assert!(a.in_release);
+        assert_eq!(a.series, "trixie");
+        assert_eq!(a.release_label(), "debian trixie");
+        // the NEW queue only feeds unstable
+        let d = find(&graph, "d").debinfo.as_ref().unwrap();
+        assert!(!d.in_release);
+        assert!(d.in_new);
+        assert_eq!(
+            d.new_queue_label(),
+            "debian NEW queue, not in debian trixie"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prefer_better_matches_from_apt_indices() -> Result<(), Error> {
+        let index = AptIndex::parse(
+            "http://apt.example.com/Packages",
+            "\
+Package: librust-a-dev
+Source: rust-a
+Version: 1.0.0-1
+
+Package: librust-c-dev
+Source: rust-c
+Version: 1.0.0-1
+
+Package: librust-d-dev
+Source: rust-d
+Version: 1.0.0-1
+",
+        );
+        let mut graph = flat_graph()?;
+        populate(&mut graph, &LookupOptions::default(), &|| {
+            Ok(leak_mock_connection(mock_responses()).with_apt_indices(vec![index.clone()]))
+        })?;
+
+        // debian has the exact version as well and is checked first
+        let a = find(&graph, "a").debinfo.as_ref().unwrap();
+        assert!(a.in_archive());
+        assert_eq!(a.origin, None);
+        assert_eq!(a.release_label(), "debian");
+        // only the index has the version in use
+        for name in ["c", "d"] {
+            let pkg = find(&graph, name);
+            assert_eq!(pkg.packaging_status(), PackagingProgress::Available);
+            let deb = pkg.debinfo.as_ref().unwrap();
+            assert!(deb.in_release);
+            assert!(!deb.in_archive());
+            assert_eq!(deb.version, "1.0.0");
+            assert_eq!(
+                deb.origin.as_deref(),
+                Some("http://apt.example.com/Packages")
+            );
+            assert_eq!(deb.release_label(), "http://apt.example.com/Packages");
+        }
+        Ok(())
+    }
 }
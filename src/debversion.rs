@@ -0,0 +1,287 @@
+use crate::errors::*;
+use semver::{Prerelease, Version};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A Debian package version, `[epoch:]upstream_version[-debian_revision]`
+///
+/// See <https://www.debian.org/doc/debian-policy/ch-controlfields.html#version>
+///
+/// Versions are ordered like `dpkg --compare-versions` does.
+#[derive(Debug, Clone)]
+pub struct DebVersion {
+    pub epoch: u32,
+    pub upstream: String,
+    pub revision: Option<String>,
+}
+
+impl FromStr for DebVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DebVersion, Error> {
+        let (epoch, rest) = match s.split_once(':') {
+            Some((epoch, rest)) => {
+                let epoch = epoch
+                    .parse()
+                    .with_context(|| format!("invalid epoch in debian version: {s:?}"))?;
+                (epoch, rest)
+            }
+            None => (0, s),
+        };
+
+        // the revision starts after the last hyphen, the upstream version may contain more
+        let (upstream, revision) = match rest.rsplit_once('-') {
+            Some((upstream, revision)) => (upstream, Some(revision)),
+            None => (rest, None),
+        };
+
+        if !upstream.starts_with(|c: char| c.is_ascii_digit()) {
+            bail!("upstream version must start with a digit: {s:?}");
+        }
+        if let Some(c) = upstream
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !".+-~:".contains(*c))
+        {
+            bail!("invalid character {c:?} in upstream version: {s:?}");
+        }
+        if let Some(revision) = revision
+            && (revision.is_empty()
+                || revision
+                    .chars()
+                    .any(|c| !c.is_ascii_alphanumeric() && !".+~".contains(c)))
+        {
+            bail!("invalid debian revision: {s:?}");
+        }
+
+        Ok(DebVersion {
+            epoch,
+            upstream: upstream.to_string(),
+            revision: revision.map(String::from),
+        })
+    }
+}
+
+impl fmt::Display for DebVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.epoch != 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if let Some(revision) = &self.revision {
+            write!(f, "-{revision}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for DebVersion {
+    fn cmp(&self, other: &DebVersion) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| verrevcmp(&self.upstream, &other.upstream))
+            .then_with(|| {
+                verrevcmp(
+                    self.revision.as_deref().unwrap_or_default(),
+                    other.revision.as_deref().unwrap_or_default(),
+                )
+            })
+    }
+}
+
+impl PartialOrd for DebVersion {
+    fn partial_cmp(&self, other: &DebVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DebVersion {
+    fn eq(&self, other: &DebVersion) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DebVersion {}
+
+// Sort weight of a character in the non-digit parts of a version, `~` sorts
+// before everything (even the end of the string), letters before other symbols
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(b'~') => -1,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+// Port of verrevcmp() from dpkg's lib/dpkg/version.c
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let ac = order(a.get(i).copied());
+            let bc = order(b.get(j).copied());
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+
+        let mut first_diff = Ordering::Equal;
+        while i < a.len() && a[i].is_ascii_digit() && j < b.len() && b[j].is_ascii_digit() {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        if i < a.len() && a[i].is_ascii_digit() {
+            return Ordering::Greater;
+        }
+        if j < b.len() && b[j].is_ascii_digit() {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+impl DebVersion {
+    /// Map the upstream version to the crate version it was packaged from
+    pub fn to_semver(&self) -> Result<Version, Error> {
+        upstream_to_semver(&self.upstream)
+    }
+}
+
+/// Map an upstream version to the crate version it was packaged from
+///
+/// Repacks (`+dfsg`, `+ds`) and snapshots (`+git20231122`) are dropped, a
+/// `~` starts a pre-release and missing minor or patch versions are zero.
+pub fn upstream_to_semver(upstream: &str) -> Result<Version, Error> {
+    let version = match upstream.split_once('+') {
+        Some((version, _suffix)) => version,
+        None => upstream,
+    };
+
+    let (core, pre) = match version.split_once(['~', '-']) {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (version, None),
+    };
+
+    let numbers = core
+        .split('.')
+        .map(|n| n.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("upstream version isn't numeric: {upstream:?}"))?;
+    let mut semver = match numbers[..] {
+        [major] => Version::new(major, 0, 0),
+        [major, minor] => Version::new(major, minor, 0),
+        [major, minor, patch] => Version::new(major, minor, patch),
+        _ => bail!("wrong number of '.' characters in semver string: {upstream:?}"),
+    };
+
+    if let Some(pre) = pre {
+        semver.pre = Prerelease::new(&pre.replace('~', "-"))
+            .with_context(|| format!("invalid pre-release version: {upstream:?}"))?;
+    }
+
+    Ok(semver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DebVersion;
+    use semver::Version;
+    use std::cmp::Ordering;
+
+    fn compare(a: &str, b: &str) -> Ordering {
+        a.parse::<DebVersion>()
+            .unwrap()
+            .cmp(&b.parse::<DebVersion>().unwrap())
+    }
+
+    fn semver(debversion: &str) -> Version {
+        debversion
+            .parse::<DebVersion>()
+            .unwrap()
+            .to_semver()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_epoch_and_revision() {
+        let v: DebVersion = "1:2.0.0-1".parse().unwrap();
+        assert_eq!(v.epoch, 1);
+        assert_eq!(v.upstream, "2.0.0");
+        assert_eq!(v.revision.as_deref(), Some("1"));
+        assert_eq!(v.to_string(), "1:2.0.0-1");
+
+        let v: DebVersion = "1.0.0-beta.1-2+b1".parse().unwrap();
+        assert_eq!(v.epoch, 0);
+        assert_eq!(v.upstream, "1.0.0-beta.1");
+        assert_eq!(v.revision.as_deref(), Some("2+b1"));
+
+        let v: DebVersion = "0.4.3".parse().unwrap();
+        assert_eq!(v.revision, None);
+    }
+
+    #[test]
+    fn reject_invalid_versions() {
+        assert!("".parse::<DebVersion>().is_err());
+        assert!("a1.0-1".parse::<DebVersion>().is_err());
+        assert!("x:1.0-1".parse::<DebVersion>().is_err());
+        assert!("1.0-".parse::<DebVersion>().is_err());
+        assert!("1.0_2-1".parse::<DebVersion>().is_err());
+    }
+
+    #[test]
+    fn map_to_semver() {
+        assert_eq!(semver("1:2.0.0-1"), Version::parse("2.0.0").unwrap());
+        assert_eq!(semver("4+dfsg-2"), Version::parse("4.0.0").unwrap());
+        assert_eq!(semver("4+20231122+dfsg"), Version::parse("4.0.0").unwrap());
+        assert_eq!(semver("0.3+ds-1"), Version::parse("0.3.0").unwrap());
+        assert_eq!(semver("1.2.3~rc1-1"), Version::parse("1.2.3-rc1").unwrap());
+        assert_eq!(
+            semver("1.0.0-beta.1-2"),
+            Version::parse("1.0.0-beta.1").unwrap()
+        );
+        assert_eq!(semver("1.02.3-1"), Version::parse("1.2.3").unwrap());
+        assert!(
+            "1.2.3.4-1"
+                .parse::<DebVersion>()
+                .unwrap()
+                .to_semver()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn compare_like_dpkg() {
+        assert_eq!(compare("1.0-1", "1.0-1"), Ordering::Equal);
+        assert_eq!(compare("1.0", "1.0-0"), Ordering::Equal);
+        assert_eq!(compare("1.0", "1.00"), Ordering::Equal);
+        assert_eq!(compare("1:0.1-1", "2.0-1"), Ordering::Greater);
+        assert_eq!(compare("1.0~rc1-1", "1.0-1"), Ordering::Less);
+        assert_eq!(compare("1.0~~", "1.0~"), Ordering::Less);
+        assert_eq!(compare("1.0+dfsg-1", "1.0-1"), Ordering::Greater);
+        assert_eq!(compare("1.0a-1", "1.0+-1"), Ordering::Less);
+        assert_eq!(compare("1.10.0-1", "1.9.0-2"), Ordering::Greater);
+        assert_eq!(compare("0.4.3-5", "0.4.3-10"), Ordering::Less);
+        assert_eq!(compare("2.0.0-1+b1", "2.0.0-1"), Ordering::Greater);
+    }
+}
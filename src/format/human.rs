@@ -1,92 +1,267 @@
+use crate::config::AnnotationKind;
+use crate::db::Candidate;
 use crate::errors::*;
-use crate::format::{Chunk, Pattern, Pkg};
+use crate::format::parse::{Alignment, Spec};
+use crate::format::{Chunk, Pattern, Pkg, Placeholder};
 use colored::Colorize;
 use std::fmt::Write;
+use unicode_width::UnicodeWidthStr;
 
 pub fn display(pattern: &Pattern, package: &Pkg) -> Result<String, Error> {
     let mut fmt = String::new();
+    write_chunks(&mut fmt, &pattern.0, package)?;
+    Ok(fmt)
+}
+
+fn write_chunks(fmt: &mut String, chunks: &[Chunk], package: &Pkg) -> Result<(), Error> {
+    for chunk in chunks {
+        match chunk {
+            Chunk::Raw(s) => fmt.write_str(s)?,
+            Chunk::Placeholder(placeholder, None) => {
+                fmt.write_str(&placeholder_value(*placeholder, package)?)?
+            }
+            Chunk::Placeholder(placeholder, Some(spec)) => {
+                let value = placeholder_value(*placeholder, package)?;
+                pad(fmt, &value, spec)?;
+            }
+            Chunk::Conditional(placeholder, chunks) => {
+                if !placeholder_value(*placeholder, package)?.is_empty() {
+                    write_chunks(fmt, chunks, package)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn placeholder_value(placeholder: Placeholder, package: &Pkg) -> Result<String, Error> {
+    let mut fmt = String::new();
 
-    for chunk in &pattern.0 {
-        match *chunk {
-            Chunk::Raw(ref s) => fmt.write_str(s)?,
-            Chunk::Package => {
-                let pkg = format!("{} v{}", package.name, package.version);
-                if let Some(deb) = &package.debinfo {
-                    if deb.in_unstable {
-                        if deb.compatible {
-                            write!(fmt, "{} ({} in debian)", pkg.green(), deb.version.yellow())?;
-                        } else if deb.outdated {
-                            write!(
-                                fmt,
-                                "{} (outdated, {} in debian)",
-                                pkg.yellow(),
-                                deb.version.red()
-                            )?;
-                        } else if deb.newer {
-                            write!(
-                                fmt,
-                                "{} (newer, {} in debian)",
-                                pkg.yellow(),
-                                deb.version.magenta()
-                            )?;
-                        } else {
-                            write!(fmt, "{} (in debian)", pkg.green())?;
-                        }
-                    } else if deb.in_new {
-                        if deb.compatible {
-                            write!(
-                                fmt,
-                                "{} ({} in debian NEW queue)",
-                                pkg.blue(),
-                                deb.version.yellow()
-                            )?;
-                        } else if deb.outdated {
-                            write!(
-                                fmt,
-                                "{}, (outdated, {} in debian NEW queue)",
-                                pkg.blue(),
-                                deb.version.red()
-                            )?;
-                        } else {
-                            write!(fmt, "{} (in debian NEW queue)", pkg.blue())?;
-                        }
+    match placeholder {
+        Placeholder::Package => {
+            let pkg = format!("{} v{}", package.name, package.version);
+            if let Some(annotation) = &package.annotation {
+                let kind = match annotation.kind {
+                    AnnotationKind::Ignored => "ignored",
+                    AnnotationKind::PatchedOut => "patched out",
+                };
+                write!(fmt, "{} ({kind}: {})", pkg.dimmed(), annotation.reason)?;
+            } else if let Some(deb) = &package.debinfo {
+                if deb.lockfile_stricter || deb.satisfied_by_patch {
+                    let (pkg, place) = if deb.in_release {
+                        (pkg.green(), deb.release_label())
+                    } else {
+                        (pkg.blue(), deb.new_queue_label())
+                    };
+                    let note = if deb.satisfied_by_patch {
+                        "satisfied via Debian patch"
+                    } else {
+                        "Cargo.lock is stricter than needed"
+                    };
+                    write!(fmt, "{pkg} ({} in {place}, {note})", deb.version.yellow())?;
+                } else if deb.in_release {
+                    if deb.compatible {
+                        write!(
+                            fmt,
+                            "{} ({} in {})",
+                            pkg.green(),
+                            deb.version.yellow(),
+                            deb.release_label()
+                        )?;
                     } else if deb.outdated {
-                        write!(fmt, "{} (outdated, {})", pkg.red(), deb.version.red())?;
+                        write!(
+                            fmt,
+                            "{} (outdated, {} in {})",
+                            pkg.yellow(),
+                            deb.version.red(),
+                            deb.release_label()
+                        )?;
+                    } else if deb.newer {
+                        write!(
+                            fmt,
+                            "{} (newer, {} in {})",
+                            pkg.yellow(),
+                            deb.version.magenta(),
+                            deb.release_label()
+                        )?;
                     } else {
-                        write!(fmt, "{pkg}")?;
+                        write!(fmt, "{} (in {})", pkg.green(), deb.release_label())?;
                     }
+                } else if deb.in_new {
+                    if deb.compatible {
+                        write!(
+                            fmt,
+                            "{} ({} in {})",
+                            pkg.blue(),
+                            deb.version.yellow(),
+                            deb.new_queue_label()
+                        )?;
+                    } else if deb.outdated {
+                        write!(
+                            fmt,
+                            "{}, (outdated, {} in {})",
+                            pkg.blue(),
+                            deb.version.red(),
+                            deb.new_queue_label()
+                        )?;
+                    } else {
+                        write!(fmt, "{} (in {})", pkg.blue(), deb.new_queue_label())?;
+                    }
+                } else if deb.outdated {
+                    write!(fmt, "{} (outdated, {})", pkg.red(), deb.version.red())?;
+                } else if let Some(prep) = &deb.debcargo_conf {
+                    write!(
+                        fmt,
+                        "{} ({} in debcargo-conf, {})",
+                        pkg.blue(),
+                        prep.version.yellow(),
+                        prep.distribution
+                    )?;
+                } else if let Some(bug) = &deb.wnpp {
+                    write!(fmt, "{pkg} ({})", bug.to_string().cyan())?;
                 } else {
                     write!(fmt, "{pkg}")?;
                 }
 
-                match &package.source {
-                    Some(source) if !source.is_crates_io() => write!(fmt, " ({source})")?,
-                    // https://github.com/rust-lang/cargo/issues/7483
-                    None => write!(
-                        fmt,
-                        " ({}{})",
-                        if package.workspace_member {
-                            "in workspace, "
-                        } else {
-                            ""
-                        },
-                        package.manifest_path.parent().unwrap().display()
-                    )?,
-                    _ => {}
+                if let Some(name) = &deb.packaged_as {
+                    write!(fmt, " (packaged as {})", name.cyan())?;
                 }
-            }
-            Chunk::License => {
-                if let Some(license) = &package.license {
-                    write!(fmt, "{license}")?
+                if let Some(risk) = deb.testing.as_ref().filter(|risk| risk.at_risk()) {
+                    let reasons = risk.reasons().join("; ");
+                    write!(fmt, " ({})", format!("at risk: {reasons}").red())?;
                 }
+            } else if let Some(err) = &package.lookup_error {
+                write!(fmt, "{pkg} ({})", format!("lookup failed: {err}").red())?;
+            } else {
+                write!(fmt, "{pkg}")?;
             }
-            Chunk::Repository => {
-                if let Some(repository) = &package.repository {
-                    write!(fmt, "{repository}")?
-                }
+
+            match &package.source {
+                Some(source) if !source.is_crates_io() => write!(fmt, " ({source})")?,
+                // https://github.com/rust-lang/cargo/issues/7483
+                None => write!(
+                    fmt,
+                    " ({}{})",
+                    if package.workspace_member {
+                        "in workspace, "
+                    } else {
+                        ""
+                    },
+                    package.manifest_path.parent().unwrap().display()
+                )?,
+                _ => {}
+            }
+        }
+        Placeholder::License => {
+            if let Some(license) = &package.license {
+                write!(fmt, "{license}")?
+            }
+        }
+        Placeholder::Repository => {
+            if let Some(repository) = &package.repository {
+                write!(fmt, "{repository}")?
+            }
+        }
+        Placeholder::Name => write!(fmt, "{}", package.name)?,
+        Placeholder::Version => write!(fmt, "{}", package.version)?,
+        Placeholder::DebianVersion => {
+            if let Some(deb) = &package.debinfo {
+                write!(fmt, "{}", deb.version)?
+            }
+        }
+        Placeholder::Status => write!(fmt, "{}", package.packaging_status().keyword())?,
+        Placeholder::Icon => write!(fmt, "{}", package.packaging_status())?,
+        Placeholder::Source => {
+            if let Some(source) = &package.source {
+                write!(fmt, "{source}")?
+            }
+        }
+        Placeholder::ManifestDir => {
+            write!(fmt, "{}", package.manifest_path.parent().unwrap().display())?
+        }
+        Placeholder::InNew => {
+            if package.debinfo.as_ref().is_some_and(|deb| deb.in_new) {
+                write!(fmt, "NEW")?
+            }
+        }
+        Placeholder::Features => write!(fmt, "{}", package.features.join(","))?,
+        Placeholder::Dependents => write!(fmt, "{}", package.dependents)?,
+        Placeholder::Reason => {
+            if let Some(annotation) = &package.annotation {
+                write!(fmt, "{}", annotation.reason)?
+            }
+        }
+        Placeholder::DebianSource => write_candidate_field(&mut fmt, package, |c| &c.source)?,
+        Placeholder::DebianBinary => write_candidate_field(&mut fmt, package, |c| &c.binary)?,
+        Placeholder::Suite => write_candidate_field(&mut fmt, package, |c| &c.suite)?,
+        Placeholder::Component => write_candidate_field(&mut fmt, package, |c| &c.component)?,
+        Placeholder::Wnpp => {
+            if let Some(bug) = package.debinfo.as_ref().and_then(|deb| deb.wnpp.as_ref()) {
+                write!(fmt, "{bug}")?
+            }
+        }
+        Placeholder::Risk => {
+            if let Some(risk) = package
+                .debinfo
+                .as_ref()
+                .and_then(|deb| deb.testing.as_ref())
+            {
+                write!(fmt, "{}", risk.reasons().join("; "))?
+            }
+        }
+        Placeholder::Recommendation => {
+            if let Some(action) = package.recommended_action() {
+                write!(fmt, "{action}")?
             }
         }
     }
 
     Ok(fmt)
 }
+
+// write a field of the Debian package the crate was found in, if any
+fn write_candidate_field(
+    fmt: &mut String,
+    package: &Pkg,
+    field: impl Fn(&Candidate) -> &Option<String>,
+) -> Result<(), Error> {
+    let value = package
+        .debinfo
+        .as_ref()
+        .and_then(|deb| deb.package.as_ref())
+        .and_then(|candidate| field(candidate).as_ref());
+    if let Some(value) = value {
+        write!(fmt, "{value}")?;
+    }
+    Ok(())
+}
+
+// pad a value to the requested width, ignoring terminal color codes
+fn pad(fmt: &mut String, value: &str, spec: &Spec) -> Result<(), Error> {
+    let width = visible_width(value);
+    let padding = spec.width.saturating_sub(width);
+    let (before, after) = match spec.alignment {
+        Alignment::Left => (0, padding),
+        Alignment::Right => (padding, 0),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+    };
+    write!(fmt, "{:before$}{value}{:after$}", "", "")?;
+    Ok(())
+}
+
+// the number of terminal columns a value takes up, e.g. two for most emoji
+fn visible_width(value: &str) -> usize {
+    let mut visible = String::new();
+    let mut escaped = false;
+    for ch in value.chars() {
+        if escaped {
+            escaped = !ch.is_ascii_alphabetic();
+        } else if ch == '\x1b' {
+            escaped = true;
+        } else {
+            visible.push(ch);
+        }
+    }
+    visible.width()
+}
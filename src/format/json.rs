@@ -1,5 +1,8 @@
+use crate::db::{Candidate, Distribution, RcBug};
+use crate::debcargo::Preparation;
 use crate::errors::*;
 use crate::format::Pkg;
+use crate::wnpp::WnppBug;
 
 #[derive(Debug, serde::Serialize)]
 pub struct Json {
@@ -7,31 +10,87 @@ pub struct Json {
     cargo_lock_version: String,
     repository: Option<String>,
     license: Option<String>,
+    status: &'static str,
+    /// Why this crate is intentionally not packaged
+    reason: Option<String>,
+    /// Why the Debian status couldn't be determined
+    error: Option<String>,
     debian: Option<DebianJson>,
     depth: usize,
 }
 
 #[derive(Debug, serde::Serialize)]
 pub struct DebianJson {
+    distribution: Distribution,
+    series: String,
     version: String,
     compatible: bool,
     exact_match: bool,
     in_new: bool,
+    /// Set if the crate is in the release that was looked up, not only in `origin`
+    in_release: bool,
+    /// Set if the crate is in Debian unstable, kept for existing consumers
     in_unstable: bool,
     outdated: bool,
     newer: bool,
+    packaged_as: Option<String>,
+    source: Option<String>,
+    binary: Option<String>,
+    suite: Option<String>,
+    component: Option<String>,
+    candidates: Vec<Candidate>,
+    recommendation: Option<&'static str>,
+    /// The additional APT index the crate was found in, if not the database
+    origin: Option<String>,
+    /// Human readable form of the recommendation
+    action: Option<String>,
+    /// Debian only matches the requirements of the dependents, not Cargo.lock
+    lockfile_stricter: bool,
+    /// Debian only matches the requirements of the dependents as patched in Debian
+    satisfied_by_patch: bool,
+    wnpp: Option<WnppBug>,
+    at_risk: bool,
+    rc_bugs: Vec<RcBug>,
+    migration_blocker: Option<String>,
+    debcargo_conf: Option<Preparation>,
 }
 
 impl Json {
     pub fn new(pkg: &Pkg, depth: usize) -> Self {
         let debian = pkg.debinfo.as_ref().map(|deb| DebianJson {
+            distribution: deb.distribution,
+            series: deb.series.clone(),
             version: deb.version.clone(),
             compatible: deb.compatible,
             exact_match: deb.exact_match,
             in_new: deb.in_new,
-            in_unstable: deb.in_unstable,
+            in_release: deb.in_archive(),
+            in_unstable: deb.in_archive() && deb.is_unstable(),
             outdated: deb.outdated,
             newer: deb.newer,
+            packaged_as: deb.packaged_as.clone(),
+            source: deb.package.as_ref().and_then(|p| p.source.clone()),
+            binary: deb.package.as_ref().and_then(|p| p.binary.clone()),
+            suite: deb.package.as_ref().and_then(|p| p.suite.clone()),
+            component: deb.package.as_ref().and_then(|p| p.component.clone()),
+            candidates: deb.candidates.clone(),
+            recommendation: deb.recommendation.map(|r| r.keyword()),
+            origin: deb.origin.clone(),
+            action: pkg.recommended_action(),
+            lockfile_stricter: deb.lockfile_stricter,
+            satisfied_by_patch: deb.satisfied_by_patch,
+            wnpp: deb.wnpp.clone(),
+            at_risk: deb.at_risk(),
+            rc_bugs: deb
+                .testing
+                .as_ref()
+                .map(|risk| risk.rc_bugs.clone())
+                .unwrap_or_default(),
+            migration_blocker: deb
+                .testing
+                .as_ref()
+                .and_then(|risk| risk.migration_blocker.clone()),
+            debcargo_conf: deb.debcargo_conf.clone(),
         });
 
         Json {
@@ -39,6 +98,9 @@ impl Json {
             cargo_lock_version: pkg.version.to_string(),
             repository: pkg.repository.clone(),
             license: pkg.license.clone(),
+            status: pkg.packaging_status().keyword(),
+            reason: pkg.annotation.as_ref().map(|a| a.reason.clone()),
+            error: pkg.lookup_error.clone(),
             debian,
             depth,
         }
@@ -1,6 +1,6 @@
 use crate::debian::Pkg;
 use crate::errors::*;
-use crate::format::parse::{Parser, RawChunk};
+use crate::format::parse::{Parser, RawChunk, Spec};
 
 pub mod human;
 pub mod json;
@@ -8,9 +8,68 @@ mod parse;
 
 enum Chunk {
     Raw(String),
+    Placeholder(Placeholder, Option<Spec>),
+    /// Only displayed if the placeholder isn't empty
+    Conditional(Placeholder, Vec<Chunk>),
+}
+
+#[derive(Clone, Copy)]
+enum Placeholder {
     Package,
     License,
     Repository,
+    Name,
+    Version,
+    DebianVersion,
+    Status,
+    Icon,
+    Source,
+    ManifestDir,
+    InNew,
+    Features,
+    Dependents,
+    Reason,
+    DebianSource,
+    DebianBinary,
+    Suite,
+    Component,
+    Recommendation,
+    Wnpp,
+    Risk,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Placeholder> {
+        let placeholder = match name {
+            "p" | "package" => Placeholder::Package,
+            "l" | "license" => Placeholder::License,
+            "r" | "repository" => Placeholder::Repository,
+            "n" | "name" => Placeholder::Name,
+            "v" | "version" => Placeholder::Version,
+            "d" | "debian" => Placeholder::DebianVersion,
+            "s" | "status" => Placeholder::Status,
+            "i" | "icon" => Placeholder::Icon,
+            "source" => Placeholder::Source,
+            "dir" => Placeholder::ManifestDir,
+            "new" => Placeholder::InNew,
+            "f" | "features" => Placeholder::Features,
+            "dependents" => Placeholder::Dependents,
+            "reason" => Placeholder::Reason,
+            "debsrc" => Placeholder::DebianSource,
+            "debbin" => Placeholder::DebianBinary,
+            "suite" => Placeholder::Suite,
+            "component" => Placeholder::Component,
+            "rec" | "recommendation" => Placeholder::Recommendation,
+            "wnpp" => Placeholder::Wnpp,
+            "risk" => Placeholder::Risk,
+            _ => return None,
+        };
+        Some(placeholder)
+    }
+}
+
+fn placeholder(name: &str) -> Result<Placeholder, Error> {
+    Placeholder::from_name(name).ok_or_else(|| anyhow!("unsupported pattern `{}`", name))
 }
 
 pub struct Pattern(Vec<Chunk>);
@@ -18,15 +77,23 @@ pub struct Pattern(Vec<Chunk>);
 impl Pattern {
     pub fn new(format: &str) -> Result<Pattern, Error> {
         let mut chunks = vec![];
+        // conditional segments that are still open, with the chunks preceding them
+        let mut stack = vec![];
 
         for raw in Parser::new(format) {
             let chunk = match raw {
                 RawChunk::Text(text) => Chunk::Raw(text.to_owned()),
-                RawChunk::Argument("p") => Chunk::Package,
-                RawChunk::Argument("l") => Chunk::License,
-                RawChunk::Argument("r") => Chunk::Repository,
-                RawChunk::Argument(ref a) => {
-                    return Err(anyhow!("unsupported pattern `{}`", a));
+                RawChunk::Argument(name, spec) => Chunk::Placeholder(placeholder(name)?, spec),
+                RawChunk::ConditionStart(name) => {
+                    let placeholder = placeholder(name)?;
+                    stack.push((placeholder, chunks));
+                    chunks = vec![];
+                    continue;
+                }
+                RawChunk::ConditionEnd => {
+                    let (placeholder, outer) = stack.pop().expect("parser tracks nesting");
+                    let inner = std::mem::replace(&mut chunks, outer);
+                    Chunk::Conditional(placeholder, inner)
                 }
                 RawChunk::Error(err) => return Err(anyhow!("{}", err)),
             };
@@ -3,13 +3,33 @@ use std::str;
 
 pub enum RawChunk<'a> {
     Text(&'a str),
-    Argument(&'a str),
+    Argument(&'a str, Option<Spec>),
+    /// Start of a conditional segment, e.g. `{?d: (debian {d})}`
+    ConditionStart(&'a str),
+    /// End of the innermost conditional segment
+    ConditionEnd,
     Error(&'static str),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// Width and alignment of a placeholder, e.g. `{n:>20}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spec {
+    pub alignment: Alignment,
+    pub width: usize,
+}
+
 pub struct Parser<'a> {
     s: &'a str,
     it: iter::Peekable<str::CharIndices<'a>>,
+    /// Number of currently open conditional segments
+    depth: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -17,6 +37,7 @@ impl<'a> Parser<'a> {
         Parser {
             s,
             it: s.char_indices().peekable(),
+            depth: 0,
         }
     }
 
@@ -31,7 +52,39 @@ impl<'a> Parser<'a> {
     }
 
     fn argument(&mut self) -> RawChunk<'a> {
-        RawChunk::Argument(self.name())
+        let name = self.name();
+        if !self.consume(':') {
+            return RawChunk::Argument(name, None);
+        }
+
+        match self.spec() {
+            Some(spec) => RawChunk::Argument(name, Some(spec)),
+            None => RawChunk::Error("invalid width or alignment"),
+        }
+    }
+
+    fn spec(&mut self) -> Option<Spec> {
+        let alignment = if self.consume('<') {
+            Alignment::Left
+        } else if self.consume('>') {
+            Alignment::Right
+        } else if self.consume('^') {
+            Alignment::Center
+        } else {
+            Alignment::Left
+        };
+
+        let mut width = String::new();
+        while let Some(&(_, ch)) = self.it.peek() {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            width.push(ch);
+            self.it.next();
+        }
+
+        let width = width.parse().ok()?;
+        Some(Spec { alignment, width })
     }
 
     fn name(&mut self) -> &'a str {
@@ -57,7 +110,7 @@ impl<'a> Parser<'a> {
     fn text(&mut self, start: usize) -> RawChunk<'a> {
         while let Some(&(pos, ch)) = self.it.peek() {
             match ch {
-                '{' | '}' | ')' => return RawChunk::Text(&self.s[start..pos]),
+                '{' | '}' => return RawChunk::Text(&self.s[start..pos]),
                 _ => {
                     self.it.next();
                 }
@@ -76,6 +129,15 @@ impl<'a> Iterator for Parser<'a> {
                 self.it.next();
                 if self.consume('{') {
                     Some(RawChunk::Text("{"))
+                } else if self.consume('?') {
+                    let name = self.name();
+                    if self.consume(':') {
+                        self.depth += 1;
+                        Some(RawChunk::ConditionStart(name))
+                    } else {
+                        for _ in &mut self.it {}
+                        Some(RawChunk::Error("expected ':'"))
+                    }
                 } else {
                     let chunk = self.argument();
                     if self.consume('}') {
@@ -88,9 +150,18 @@ impl<'a> Iterator for Parser<'a> {
             }
             Some(&(_, '}')) => {
                 self.it.next();
-                Some(RawChunk::Error("unexpected '}'"))
+                if self.depth > 0 {
+                    self.depth -= 1;
+                    Some(RawChunk::ConditionEnd)
+                } else {
+                    Some(RawChunk::Error("unexpected '}'"))
+                }
             }
             Some(&(i, _)) => Some(self.text(i)),
+            None if self.depth > 0 => {
+                self.depth = 0;
+                Some(RawChunk::Error("expected '}'"))
+            }
             None => None,
         }
     }
@@ -1,31 +1,88 @@
-use crate::args::Args;
+use crate::config::Annotation;
 use crate::debian::Pkg;
 use crate::errors::*;
 use cargo_metadata::{DependencyKind, Metadata, PackageId};
+use petgraph::EdgeDirection;
 use petgraph::graph::NodeIndex;
 use petgraph::stable_graph::StableGraph;
 use petgraph::visit::Dfs;
+use semver::{Comparator, Op, Version, VersionReq};
 use std::collections::{HashMap, HashSet};
 
 pub struct Graph {
-    pub graph: StableGraph<Pkg, DependencyKind>,
+    pub graph: StableGraph<Pkg, Edge>,
     pub nodes: HashMap<PackageId, NodeIndex>,
     pub roots: Vec<PackageId>,
+    /// Version requirements on each package by all of its dependents, including
+    /// the ones hidden by `--edges` or pruned from the graph, as Cargo.lock
+    /// has to satisfy them too
+    pub lockfile_requirements: HashMap<PackageId, Vec<VersionReq>>,
 }
 
-pub fn build(args: &Args, metadata: Metadata) -> Result<Graph, Error> {
+/// A dependency of a package on another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub kind: DependencyKind,
+    /// Version requirement of the dependent, as written in its `Cargo.toml`
+    pub req: VersionReq,
+}
+
+/// What to include in a dependency graph, e.g. from the command line with
+/// [`Args::graph_options`](crate::Args::graph_options)
+#[derive(Debug, Clone)]
+pub struct GraphOptions {
+    /// Dependency kinds to add edges for
+    pub dependency_kinds: Vec<DependencyKind>,
+    /// Only keep these workspace members as roots
+    pub included: Option<Vec<String>>,
+    /// Drop these workspace members from the roots, unless `included` is set
+    pub excluded: Option<Vec<String>>,
+    /// Hide the dependency trees of workspace members which are dependencies of other members
+    pub collapse_workspace: bool,
+    /// Crates that are intentionally not packaged
+    pub ignore: Vec<Annotation>,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        GraphOptions {
+            dependency_kinds: vec![
+                DependencyKind::Normal,
+                DependencyKind::Build,
+                DependencyKind::Development,
+            ],
+            included: None,
+            excluded: None,
+            collapse_workspace: false,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// Build the dependency graph of the workspace described by `metadata`
+pub fn build(options: &GraphOptions, metadata: Metadata) -> Result<Graph, Error> {
     let resolve = metadata.resolve.unwrap();
 
     let mut graph = Graph {
         graph: StableGraph::new(),
         nodes: HashMap::new(),
         roots: metadata.workspace_members.clone(),
+        lockfile_requirements: HashMap::new(),
     };
 
-    for package in metadata.packages {
+    // the declared dependencies of each package, to look up version requirements
+    let mut declared = HashMap::new();
+    for mut package in metadata.packages {
         let id = package.id.clone();
+        declared.insert(id.clone(), std::mem::take(&mut package.dependencies));
         let workspace_member = metadata.workspace_members.contains(&package.id);
-        let index = graph.graph.add_node(Pkg::new(package, workspace_member));
+        let mut pkg = Pkg::new(package, workspace_member);
+        pkg.annotation = options
+            .ignore
+            .iter()
+            .find(|annotation| annotation.matches(&pkg.name, &pkg.version))
+            .cloned();
+        let index = graph.graph.add_node(pkg);
         graph.nodes.insert(id, index);
     }
 
@@ -35,6 +92,7 @@ pub fn build(args: &Args, metadata: Metadata) -> Result<Graph, Error> {
         }
 
         let from = graph.nodes[&node.id];
+        graph.graph[from].features = node.features.iter().map(|f| f.to_string()).collect();
         for dep in node.deps {
             if dep.dep_kinds.is_empty() {
                 return Err(anyhow!("cargo tree requires cargo 1.41 or newer"));
@@ -50,35 +108,48 @@ pub fn build(args: &Args, metadata: Metadata) -> Result<Graph, Error> {
 
             let to = graph.nodes[&dep.pkg];
             for kind in kinds {
-                if args.no_dev_dependencies && kind == DependencyKind::Development {
-                    continue;
+                let target = &graph.graph[to];
+                let req = declared[&node.id]
+                    .iter()
+                    .find(|d| {
+                        d.kind == kind && d.name == target.name && d.req.matches(&target.version)
+                    })
+                    .map(|d| d.req.clone())
+                    .unwrap_or_else(|| locked_req(&target.version));
+
+                let requirements = graph
+                    .lockfile_requirements
+                    .entry(dep.pkg.clone())
+                    .or_default();
+                if !requirements.contains(&req) {
+                    requirements.push(req.clone());
                 }
 
-                graph.graph.add_edge(from, to, kind);
+                if options.dependency_kinds.contains(&kind) {
+                    graph.graph.add_edge(from, to, Edge { kind, req });
+                }
             }
         }
     }
 
-    if let Some(included) = &args.included {
+    if let Some(included) = &options.included {
         // only keep roots included on the command line
-        let included: Vec<&str> = included.split(",").collect();
-        let included_roots: Vec<PackageId> = resolve_roots(&graph, &included);
+        let included_roots: Vec<PackageId> = resolve_roots(&graph, included);
         graph.roots.retain(|root| included_roots.contains(root));
 
         // optionally collapse workspace
-        if args.collapse_workspace {
+        if options.collapse_workspace {
             collapse_workspace(&mut graph);
         }
     } else {
         // optionally collapse workspace
-        if args.collapse_workspace {
+        if options.collapse_workspace {
             collapse_workspace(&mut graph);
         }
 
         // prune roots excluded on the command line
-        if let Some(excluded) = &args.excluded {
-            let excluded: Vec<&str> = excluded.split(",").collect();
-            let excluded_roots: Vec<PackageId> = resolve_roots(&graph, &excluded);
+        if let Some(excluded) = &options.excluded {
+            let excluded_roots: Vec<PackageId> = resolve_roots(&graph, excluded);
             graph.roots.retain(|root| !excluded_roots.contains(root));
         }
     }
@@ -100,9 +171,39 @@ pub fn build(args: &Args, metadata: Metadata) -> Result<Graph, Error> {
         }
     });
 
+    for idx in graph.nodes.values() {
+        let dependents = graph
+            .graph
+            .neighbors_directed(*idx, EdgeDirection::Incoming)
+            .collect::<HashSet<_>>();
+
+        let pkg = &mut graph.graph[*idx];
+        pkg.dependents = dependents.len();
+        // a version in Debian has to do for the dependents that aren't displayed too
+        pkg.requirements = graph
+            .lockfile_requirements
+            .get(&pkg.id)
+            .cloned()
+            .unwrap_or_default();
+    }
+
     Ok(graph)
 }
 
+// The requirement to assume if the declared one can't be found, the locked
+// version is known to work, anything semver compatible is likely to as well
+fn locked_req(version: &Version) -> VersionReq {
+    VersionReq {
+        comparators: vec![Comparator {
+            op: Op::Caret,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: version.pre.clone(),
+        }],
+    }
+}
+
 // prune roots reachable from other roots (directionally), that is,
 // do not count as roots workspace members which are dependencies
 // of other workspace members
@@ -121,20 +222,11 @@ fn collapse_workspace(graph: &mut Graph) {
     graph.roots.retain(|root| !disc.contains(root));
 }
 
-fn resolve_roots(graph: &Graph, roots: &[&str]) -> Vec<PackageId> {
+fn resolve_roots(graph: &Graph, roots: &[String]) -> Vec<PackageId> {
     graph
         .roots
         .iter()
-        .filter(|root| {
-            roots.contains(
-                &graph
-                    .graph
-                    .node_weight(graph.nodes[root])
-                    .unwrap()
-                    .name
-                    .as_str(),
-            )
-        })
+        .filter(|root| roots.contains(&graph.graph.node_weight(graph.nodes[root]).unwrap().name))
         .cloned()
         .collect()
 }
@@ -0,0 +1,66 @@
+//! cargo-tree for debian packaging
+//!
+//! Besides the `cargo debstatus` subcommand, this crate can be used to check
+//! the packaging status of a dependency graph from other tools:
+//!
+//! ```no_run
+//! use cargo_debstatus::{Connection, GraphOptions, LookupOptions, build_graph, populate};
+//! use cargo_metadata::MetadataCommand;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let metadata = MetadataCommand::new().exec()?;
+//! let mut graph = build_graph(&GraphOptions::default(), metadata)?;
+//! let options = LookupOptions {
+//!     quiet: true,
+//!     ..LookupOptions::default()
+//! };
+//! populate(&mut graph, &options, &Connection::new)?;
+//!
+//! for pkg in graph.graph.node_weights() {
+//!     println!("{} v{}: {}", pkg.name, pkg.version, pkg.packaging_status().keyword());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Crates can be looked up somewhere else than the Debian database by passing
+//! [`Connection::with_archive`] with a [`LocalRegistry`] to [`populate`], or
+//! a database with the same schema through [`Client`] and
+//! [`Connection::with_client`]. To stop the lookups early, e.g. on Ctrl-C, use
+//! [`populate_until`] instead.
+
+mod apt;
+mod args;
+// the `cargo debstatus` binary, not part of the library
+#[doc(hidden)]
+pub mod cli;
+mod config;
+mod db;
+mod debcargo;
+mod debian;
+mod debversion;
+mod errors;
+mod format;
+mod graph;
+mod lockfile;
+mod metadata;
+mod pool;
+mod registry;
+mod resolve;
+mod tree;
+mod wnpp;
+
+pub use crate::apt::AptIndex;
+pub use crate::args::{Args, Charset, ColorMode, EdgeKind};
+pub use crate::config::{Annotation, AnnotationKind, CrateConfig};
+pub use crate::db::{
+    Archive, Candidate, Client, Connection, Distribution, NameOverride, RcBug, Recommendation,
+    TestingRisk,
+};
+pub use crate::debcargo::Preparation;
+pub use crate::debian::{
+    DebianInfo, LookupOptions, PackagingProgress, Pkg, populate, populate_until,
+};
+pub use crate::graph::{Edge, Graph, GraphOptions, build as build_graph};
+pub use crate::registry::LocalRegistry;
+pub use crate::wnpp::WnppBug;
@@ -0,0 +1,144 @@
+use crate::args::Args;
+use crate::debversion::upstream_to_semver;
+use crate::errors::*;
+use crate::graph::Graph;
+use log::warn;
+use semver::Version;
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+
+/// A `cargo update --precise` that makes Cargo.lock use the version in Debian
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PreciseUpdate {
+    pub name: String,
+    /// Version currently in Cargo.lock
+    pub from: Version,
+    /// Version available in Debian
+    pub to: Version,
+}
+
+impl fmt::Display for PreciseUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cargo update -p {}@{} --precise {}",
+            self.name, self.from, self.to
+        )
+    }
+}
+
+/// Find crates.io dependencies that could be switched to the version in
+/// Debian without violating the requirements of any dependent
+pub fn suggest(graph: &Graph) -> Vec<PreciseUpdate> {
+    let mut updates = vec![];
+
+    for idx in graph.nodes.values() {
+        let pkg = &graph.graph[*idx];
+        if !pkg.source.as_ref().is_some_and(|s| s.is_crates_io()) {
+            continue;
+        }
+
+        let Some(deb) = &pkg.debinfo else {
+            continue;
+        };
+        if !deb.in_release && !deb.in_new {
+            continue;
+        }
+        let debversion = match upstream_to_semver(&deb.version) {
+            Ok(version) => version,
+            Err(err) => {
+                warn!("Skipping {} v{}: {err:#}", pkg.name, pkg.version);
+                continue;
+            }
+        };
+        if debversion == pkg.version {
+            continue;
+        }
+
+        let satisfied = graph
+            .lockfile_requirements
+            .get(&pkg.id)
+            .into_iter()
+            .flatten()
+            .all(|req| req.matches(&debversion));
+        if satisfied {
+            updates.push(PreciseUpdate {
+                name: pkg.name.clone(),
+                from: pkg.version.clone(),
+                to: debversion,
+            });
+        }
+    }
+
+    // ensure a consistent output ordering
+    updates.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.from.cmp(&b.from)));
+    updates
+}
+
+pub fn print<W: Write>(args: &Args, graph: &Graph, writer: &mut W) -> Result<(), Error> {
+    for update in suggest(graph) {
+        if args.json {
+            #[derive(Serialize)]
+            struct Json<'a> {
+                #[serde(flatten)]
+                update: &'a PreciseUpdate,
+                command: String,
+            }
+
+            let json = Json {
+                update: &update,
+                command: update.to_string(),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&json)?)?;
+        } else {
+            writeln!(writer, "{update}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PreciseUpdate, suggest};
+    use crate::args::Args;
+    use crate::tree::tests::{hidden_dev_dependency, new_mock_connection, registry_metadata};
+    use crate::{debian, graph};
+    use anyhow::Error;
+    use clap::Parser;
+    use semver::Version;
+
+    #[test]
+    fn suggest_precise_updates() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w"]);
+        let metadata = registry_metadata(&[("b", "^1")])?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+
+        assert_eq!(
+            suggest(&graph),
+            vec![PreciseUpdate {
+                name: "c".to_string(),
+                from: Version::new(1, 0, 0),
+                to: Version::new(0, 4, 5),
+            }]
+        );
+        assert_eq!(
+            suggest(&graph)[0].to_string(),
+            "cargo update -p c@1.0.0 --precise 0.4.5"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn respect_hidden_dependency_kinds() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--edges", "normal,build"]);
+        let metadata = hidden_dev_dependency(registry_metadata(&[("b", "^1")])?)?;
+
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+
+        assert_eq!(suggest(&graph), vec![]);
+        Ok(())
+    }
+}
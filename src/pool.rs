@@ -0,0 +1,471 @@
+use crate::db::{Archive, CancelHandle, Connection};
+use crate::errors::*;
+use log::warn;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub const RETRIES: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Database connections shared by the lookup workers
+///
+/// Connections are only opened once no idle one is left, so there are never
+/// more of them than lookups running at the same time, i.e. workers.
+pub struct Pool<'a, C: Archive> {
+    new_connection: &'a (dyn Fn() -> Result<Connection<C>, Error> + Sync),
+    retries: u32,
+    base_delay: Duration,
+    state: Mutex<State<C>>,
+    cancelled: Condvar,
+}
+
+struct State<C: Archive> {
+    idle: Vec<Connection<C>>,
+    /// Number of connections opened so far
+    opened: usize,
+    /// Handles to abort the lookups in progress, by lookup
+    running: HashMap<usize, CancelHandle>,
+    next_lookup: usize,
+    cancelled: bool,
+}
+
+impl<'a, C: Archive> Pool<'a, C> {
+    pub fn new(new_connection: &'a (dyn Fn() -> Result<Connection<C>, Error> + Sync)) -> Self {
+        Pool {
+            new_connection,
+            retries: RETRIES,
+            base_delay: BASE_DELAY,
+            state: Mutex::new(State {
+                idle: Vec::new(),
+                opened: 0,
+                running: HashMap::new(),
+                next_lookup: 0,
+                cancelled: false,
+            }),
+            cancelled: Condvar::new(),
+        }
+    }
+
+    /// Change how often transient errors are retried
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Change the delay before the first retry, which doubles with every attempt
+    #[cfg(test)]
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Number of connections opened so far, including broken ones that were dropped
+    #[cfg(test)]
+    pub fn opened(&self) -> usize {
+        self.state.lock().unwrap().opened
+    }
+
+    /// Stop handing out connections and abort the queries that are running,
+    /// lookups waiting for a connection or a retry fail right away
+    pub fn cancel(&self) {
+        let running = {
+            let mut state = self.state.lock().unwrap();
+            state.cancelled = true;
+            mem::take(&mut state.running)
+        };
+        self.cancelled.notify_all();
+        // cancelling can block on the network, don't hold up the workers meanwhile
+        for cancel in running.values() {
+            cancel();
+        }
+    }
+
+    /// Run a lookup with a connection from the pool, retrying it with a fresh
+    /// connection if it fails for reasons that might go away
+    pub fn run<T>(
+        &self,
+        mut lookup: impl FnMut(&mut Connection<C>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut attempt = 0;
+        loop {
+            match self.try_run(&mut lookup) {
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    let delay = self.backoff(attempt);
+                    warn!("{err:#}, retrying in {delay:?}");
+                    self.sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn try_run<T>(
+        &self,
+        lookup: &mut impl FnMut(&mut Connection<C>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let (id, mut db) = self.acquire()?;
+        let result = lookup(&mut db);
+        // a connection that failed this way is likely broken
+        let broken = result.as_ref().is_err_and(is_transient);
+        self.release(Some(id), (!broken).then_some(db));
+        result
+    }
+
+    fn acquire(&self) -> Result<(usize, Connection<C>), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.cancelled {
+            bail!("lookup interrupted");
+        }
+        let db = match state.idle.pop() {
+            Some(db) => db,
+            None => {
+                state.opened += 1;
+                let opened = state.opened;
+                drop(state);
+
+                debug!("Opening database connection #{opened}");
+                let db = (self.new_connection)()?;
+                state = self.state.lock().unwrap();
+                db
+            }
+        };
+
+        // the pool may have been cancelled while connecting
+        if state.cancelled {
+            drop(state);
+            self.release(None, Some(db));
+            bail!("lookup interrupted");
+        }
+        let id = state.next_lookup;
+        state.next_lookup += 1;
+        if let Some(cancel) = db.cancel_handle() {
+            state.running.insert(id, cancel);
+        }
+        Ok((id, db))
+    }
+
+    fn release(&self, id: Option<usize>, db: Option<Connection<C>>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(id) = id {
+            state.running.remove(&id);
+        }
+        state.idle.extend(db);
+    }
+
+    // Wait before retrying, unless the pool is cancelled in the meantime
+    fn sleep(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        let mut state = self.state.lock().unwrap();
+        while !state.cancelled {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            state = self.cancelled.wait_timeout(state, left).unwrap().0;
+        }
+    }
+
+    // Exponential backoff with jitter, so workers don't retry all at once
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY);
+        delay / 2 + delay * fastrand::u32(..1000) / 2000
+    }
+}
+
+/// Check if an error might go away when retrying, e.g. a dropped connection
+/// or an overloaded server
+pub fn is_transient(err: &Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        if let Some(err) = cause.downcast_ref::<postgres::Error>() {
+            if err.is_closed() {
+                return true;
+            }
+            // connection exceptions, insufficient resources, operator
+            // intervention and failed transactions that can be retried
+            return err.code().is_some_and(|code| {
+                let code = code.code();
+                ["08", "53", "57P", "40001", "40P01"]
+                    .iter()
+                    .any(|prefix| code.starts_with(prefix))
+            });
+        }
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_DELAY, Pool, is_transient};
+    use crate::args::Args;
+    use crate::db::tests::{MockClient, mock_client, mock_connection};
+    use crate::db::{CancelHandle, Client, Connection, PkgStatus};
+    use crate::debian::PackagingProgress;
+    use crate::{debian, graph};
+    use anyhow::{Error, anyhow};
+    use cargo_metadata::Metadata;
+    use clap::Parser;
+    use crossbeam_channel::{Receiver, Sender};
+    use semver::Version;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    // Blocks in every query until it's cancelled
+    struct BlockingClient {
+        started: Sender<()>,
+        cancel_tx: Sender<()>,
+        cancel_rx: Receiver<()>,
+    }
+
+    impl Client for BlockingClient {
+        fn run_query(&mut self, _: &str, _: &[&str]) -> Result<Vec<Vec<String>>, Error> {
+            self.started.send(()).unwrap();
+            self.cancel_rx.recv().unwrap();
+            Err(anyhow!("canceling statement due to user request"))
+        }
+
+        fn cancel_handle(&self) -> Option<CancelHandle> {
+            let cancel_tx = self.cancel_tx.clone();
+            Some(Box::new(move || cancel_tx.send(()).unwrap()))
+        }
+    }
+
+    // Interrupts the run after its first query
+    struct InterruptingClient<'a> {
+        client: MockClient<'a>,
+        interrupted: &'a AtomicBool,
+    }
+
+    impl Client for InterruptingClient<'_> {
+        fn run_query(&mut self, query: &str, params: &[&str]) -> Result<Vec<Vec<String>>, Error> {
+            let rows = self.client.run_query(query, params);
+            self.interrupted.store(true, Ordering::SeqCst);
+            rows
+        }
+    }
+
+    fn refused() -> Error {
+        Error::new(io::Error::from(io::ErrorKind::ConnectionRefused))
+            .context("error connecting to server")
+    }
+
+    #[test]
+    fn classify_transient_errors() {
+        assert!(is_transient(&refused()));
+        assert!(!is_transient(&anyhow!("Unmocked SQL query")));
+        assert!(!is_transient(&Error::new(io::Error::from(
+            io::ErrorKind::NotFound
+        ))));
+    }
+
+    #[test]
+    fn retry_with_new_connections() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let responses = [(
+            "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+            vec!["librust-serde-dev", "^librust-serde-[0-9.]+-dev$", "sid"],
+            vec![vec![
+                "1.0.219-1",
+                "rust-serde",
+                "librust-serde-dev",
+                "sid",
+                "main",
+            ]],
+        )];
+        // the first two attempts to connect fail
+        let attempts = AtomicUsize::new(0);
+        let new_connection = || -> Result<Connection<MockClient<'_>>, Error> {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                return Err(refused());
+            }
+            Ok(mock_connection(tmpdir.path(), &responses))
+        };
+        let pool = Pool::new(&new_connection)
+            .with_retries(3)
+            .with_base_delay(Duration::ZERO);
+
+        for _ in 0..3 {
+            let info = pool
+                .run(|db| db.search("serde", &Version::new(1, 0, 219), None, true))
+                .unwrap();
+            assert_eq!(info.status, PkgStatus::Found);
+        }
+        // the connection was reused once it worked
+        assert_eq!(pool.opened(), 3);
+
+        // other errors aren't retried
+        let mut lookups = 0;
+        let result = pool.run(|db| {
+            lookups += 1;
+            db.search("tokio", &Version::new(1, 0, 0), None, true)
+        });
+        assert!(result.is_err());
+        assert_eq!(lookups, 1);
+    }
+
+    #[test]
+    fn give_up_after_retries() {
+        let new_connection =
+            || -> Result<Connection<MockClient<'static>>, Error> { Err(refused()) };
+        let pool = Pool::new(&new_connection)
+            .with_retries(2)
+            .with_base_delay(Duration::ZERO);
+        let result = pool.run(|db| db.search("serde", &Version::new(1, 0, 0), None, true));
+        assert!(result.is_err());
+        assert_eq!(pool.opened(), 3);
+    }
+
+    #[test]
+    fn back_off_exponentially() {
+        let new_connection =
+            || -> Result<Connection<MockClient<'static>>, Error> { Err(refused()) };
+        let pool = Pool::new(&new_connection).with_base_delay(Duration::from_secs(1));
+        for attempt in 0..3 {
+            let delay = pool.backoff(attempt);
+            let full = Duration::from_secs(1 << attempt);
+            assert!(delay >= full / 2 && delay < full, "{delay:?}");
+        }
+        assert!(pool.backoff(10) < MAX_DELAY);
+    }
+
+    #[test]
+    fn report_failed_connections_per_crate() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--retries", "0"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_flat_dependencies.json"
+        ))?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        let new_connection =
+            || -> Result<Connection<MockClient<'static>>, Error> { Err(refused()) };
+
+        debian::populate(&mut graph, &args.lookup_options(), &new_connection)?;
+        for pkg in graph.graph.node_weights() {
+            assert!(pkg.debinfo.is_none());
+            let err = pkg.lookup_error.as_deref().unwrap();
+            assert!(err.starts_with("error connecting to server"), "{err}");
+            assert_eq!(pkg.packaging_status(), PackagingProgress::LookupFailed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_running_lookups() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let (started, started_rx) = crossbeam_channel::unbounded();
+        let (cancel_tx, cancel_rx) = crossbeam_channel::unbounded();
+        let new_connection = || -> Result<Connection<BlockingClient>, Error> {
+            let client = BlockingClient {
+                started: started.clone(),
+                cancel_tx: cancel_tx.clone(),
+                cancel_rx: cancel_rx.clone(),
+            };
+            Ok(Connection::with_client(client, tmpdir.path().to_owned()))
+        };
+        let pool = Pool::new(&new_connection);
+
+        thread::scope(|scope| {
+            let lookup = scope
+                .spawn(|| pool.run(|db| db.search("serde", &Version::new(1, 0, 0), None, true)));
+            started_rx.recv().unwrap();
+            pool.cancel();
+            let err = lookup.join().unwrap().unwrap_err();
+            assert_eq!(err.to_string(), "canceling statement due to user request");
+        });
+
+        // no more connections are handed out
+        let mut lookups = 0;
+        let err = pool
+            .run(|_| {
+                lookups += 1;
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "lookup interrupted");
+        assert_eq!(lookups, 0);
+    }
+
+    #[test]
+    fn stop_lookups_when_interrupted() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_flat_dependencies.json"
+        ))?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        let new_connection =
+            || -> Result<Connection<MockClient<'static>>, Error> { Err(anyhow!("unreachable")) };
+
+        debian::populate_until(
+            &mut graph,
+            &args.lookup_options(),
+            &new_connection,
+            &AtomicBool::new(true),
+        )?;
+        for pkg in graph.graph.node_weights() {
+            assert!(pkg.debinfo.is_none());
+            assert_eq!(pkg.lookup_error.as_deref(), Some("interrupted"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn keep_lookups_finished_when_interrupted() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "-j", "1"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_flat_dependencies.json"
+        ))?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        let tmpdir = tempfile::tempdir().unwrap();
+        // `a` is looked up first
+        let responses = [(
+            "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+            vec!["librust-a-dev", "^librust-a-[0-9.]+-dev$", "sid"],
+            vec![vec!["1.0.0-2", "rust-a", "librust-a-dev", "sid", "main"]],
+        )];
+        let interrupted = AtomicBool::new(false);
+        let new_connection = || -> Result<Connection<InterruptingClient<'_>>, Error> {
+            let client = InterruptingClient {
+                client: mock_client(&responses),
+                interrupted: &interrupted,
+            };
+            Ok(Connection::with_client(client, tmpdir.path().to_owned()))
+        };
+
+        debian::populate_until(
+            &mut graph,
+            &args.lookup_options(),
+            &new_connection,
+            &interrupted,
+        )?;
+        for pkg in graph.graph.node_weights() {
+            if pkg.name == "a" {
+                assert_eq!(pkg.packaging_status(), PackagingProgress::Available);
+            } else {
+                assert!(pkg.debinfo.is_none());
+                assert_eq!(pkg.lookup_error.as_deref(), Some("interrupted"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use crate::args::Args;
+use crate::db::{Archive, Candidate, PackageFamily, PkgType, Suite, semver_bucket};
+use crate::errors::*;
+use semver::Version;
+use std::fs;
+use std::path::Path;
+
+pub const REGISTRY_DIR: &str = "/usr/share/cargo/registry";
+pub const DPKG_STATUS: &str = "/var/lib/dpkg/status";
+
+/// A crate packaged in a registry directory, a dpkg status file or an APT index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexedPackage {
+    /// Debian version, e.g. `1.0.106-1`
+    version: String,
+    /// Source package names this crate can be looked up with
+    sources: Vec<String>,
+    /// Binary package names this crate can be looked up with, the first one
+    /// is the actual package
+    binaries: Vec<String>,
+    suite: String,
+    component: String,
+}
+
+/// Looks up crates in the ones available on this system,
+/// i.e. what would resolve in a Debian build chroot
+#[derive(Debug, Clone, Default)]
+pub struct LocalRegistry {
+    packages: Vec<IndexedPackage>,
+}
+
+impl LocalRegistry {
+    /// Load the registry directory and dpkg status file given on the command line
+    pub fn from_args(args: &Args) -> Result<Self, Error> {
+        let mut registry = LocalRegistry::default();
+        registry.scan(&args.registry_dir)?;
+        if let Some(path) = &args.dpkg_status {
+            registry.read_dpkg_status(path)?;
+        }
+        Ok(registry)
+    }
+
+    /// Scan a cargo registry directory as populated by `librust-*-dev` packages
+    pub fn scan(&mut self, dir: &Path) -> Result<(), Error> {
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("error reading registry {dir:?}"))?;
+        for entry in entries {
+            let entry = entry?;
+            let Some(dirname) = entry.file_name().to_str().map(String::from) else {
+                continue;
+            };
+            let Some((name, version)) = split_crate_dir(&dirname) else {
+                debug!("Skipping unexpected registry entry: {dirname:?}");
+                continue;
+            };
+            self.packages.push(registry_package(name, &version));
+        }
+        Ok(())
+    }
+
+    /// Read the `librust-*-dev` packages installed according to a dpkg status file
+    pub fn read_dpkg_status(&mut self, path: &Path) -> Result<(), Error> {
+        let buf = fs::read_to_string(path)
+            .with_context(|| format!("error reading dpkg status file {path:?}"))?;
+        self.parse_dpkg_status(&buf);
+        Ok(())
+    }
+
+    fn parse_dpkg_status(&mut self, buf: &str) {
+        self.packages.extend(parse_packages(buf, true));
+    }
+}
+
+impl Archive for LocalRegistry {
+    fn candidates(
+        &mut self,
+        family: &PackageFamily,
+        suite: &Suite,
+    ) -> Result<Vec<Candidate>, Error> {
+        // whatever is installed is what the build uses, regardless of the distribution
+        Ok(find_packages(&self.packages, family, suite))
+    }
+}
+
+/// Parse the `librust-*-dev` packages of a dpkg status file or an APT `Packages`
+/// index, optionally only the ones that are installed
+pub(crate) fn parse_packages(buf: &str, installed_only: bool) -> Vec<IndexedPackage> {
+    let mut packages = vec![];
+    for paragraph in paragraphs(buf) {
+        let field = |name: &str| {
+            paragraph
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+
+        let (Some(package), Some(version)) = (field("Package"), field("Version")) else {
+            continue;
+        };
+        if !package.starts_with("librust-") || !package.ends_with("-dev") {
+            continue;
+        }
+        if installed_only && !field("Status").is_some_and(|status| status.ends_with(" installed")) {
+            continue;
+        }
+
+        // the source may carry a version if it differs from the binary
+        let source = field("Source")
+            .and_then(|source| source.split_whitespace().next())
+            .unwrap_or(package);
+        let mut binaries = vec![package.to_string()];
+        binaries.extend(field("Provides").into_iter().flat_map(relation_names));
+        // indices only tell the component through the pool path, e.g. `pool/main/r/...`
+        let component = field("Filename")
+            .and_then(|filename| filename.strip_prefix("pool/"))
+            .and_then(|path| path.split('/').next())
+            .unwrap_or_default();
+
+        packages.push(IndexedPackage {
+            version: version.to_string(),
+            sources: vec![source.to_string()],
+            binaries,
+            suite: if installed_only { "installed" } else { "" }.to_string(),
+            component: component.to_string(),
+        });
+    }
+    packages
+}
+
+/// The packages of a family, see [`Archive::candidates`]
+pub(crate) fn find_packages(
+    packages: &[IndexedPackage],
+    family: &PackageFamily,
+    suite: &Suite,
+) -> Vec<Candidate> {
+    // nothing on this system or in an index is waiting in NEW
+    if *suite == Suite::New {
+        return Vec::new();
+    }
+
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    packages
+        .iter()
+        .filter_map(|package| {
+            let binary = match family.pkg_type {
+                PkgType::Binary => {
+                    if !package.binaries.iter().any(|name| family.matches(name)) {
+                        return None;
+                    }
+                    Some(package.binaries[0].clone())
+                }
+                PkgType::Source => {
+                    if !package.sources.iter().any(|name| family.matches(name)) {
+                        return None;
+                    }
+                    None
+                }
+            };
+            Some(Candidate {
+                version: package.version.clone(),
+                source: package.sources.first().cloned(),
+                binary,
+                suite: non_empty(&package.suite),
+                component: non_empty(&package.component),
+            })
+        })
+        .collect()
+}
+
+// Split a registry directory like `sha-1-0.10.1` into the crate name and version
+fn split_crate_dir(dirname: &str) -> Option<(&str, Version)> {
+    dirname
+        .match_indices('-')
+        .filter_map(|(i, _)| {
+            let version = Version::parse(&dirname[i + 1..]).ok()?;
+            Some((&dirname[..i], version))
+        })
+        .next()
+}
+
+// The package names debcargo assigns to a crate
+fn registry_package(name: &str, version: &Version) -> IndexedPackage {
+    let name = name.replace('_', "-").to_lowercase();
+    let bucket = semver_bucket(version);
+    // debian uses `~` for pre-releases, a `-` would start the revision
+    let version = version.to_string().replacen('-', "~", 1);
+
+    IndexedPackage {
+        version,
+        sources: vec![format!("rust-{name}"), format!("rust-{name}-{bucket}")],
+        binaries: vec![
+            format!("librust-{name}-dev"),
+            format!("librust-{name}-{bucket}-dev"),
+        ],
+        suite: "registry".to_string(),
+        component: String::new(),
+    }
+}
+
+/// Split a deb822 file like the dpkg status file into paragraphs of fields,
+/// continuation lines are joined with newlines
+pub(crate) fn paragraphs(buf: &str) -> Vec<Vec<(String, String)>> {
+    let mut paragraphs = vec![];
+    let mut fields: Vec<(String, String)> = vec![];
+
+    for line in buf.lines() {
+        if line.trim().is_empty() {
+            if !fields.is_empty() {
+                paragraphs.push(std::mem::take(&mut fields));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.to_string(), value.trim().to_string()));
+        }
+    }
+    if !fields.is_empty() {
+        paragraphs.push(fields);
+    }
+
+    paragraphs
+}
+
+/// The package names in a relationship field, e.g. `Provides`
+pub(crate) fn relation_names(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split([',', '|'])
+        .filter_map(|relation| relation.split_whitespace().next())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalRegistry, split_crate_dir};
+    use crate::db::{Connection, PkgStatus};
+    use semver::Version;
+    use std::fs;
+
+    #[test]
+    fn split_registry_dirs() {
+        assert_eq!(
+            split_crate_dir("serde-1.0.219"),
+            Some(("serde", Version::new(1, 0, 219)))
+        );
+        assert_eq!(
+            split_crate_dir("sha-1-0.10.1"),
+            Some(("sha-1", Version::new(0, 10, 1)))
+        );
+        assert_eq!(
+            split_crate_dir("x11-dl-2.21.0"),
+            Some(("x11-dl", Version::new(2, 21, 0)))
+        );
+        assert_eq!(
+            split_crate_dir("rand_core-0.9.0-beta.1"),
+            Some(("rand_core", Version::parse("0.9.0-beta.1").unwrap()))
+        );
+        assert_eq!(split_crate_dir("README"), None);
+    }
+
+    #[test]
+    fn find_crates_in_registry() {
+        let registry = tempfile::tempdir().unwrap();
+        fs::create_dir(registry.path().join("crossbeam-utils-0.8.21")).unwrap();
+        fs::create_dir(registry.path().join("crossbeam-channel-0.4.4")).unwrap();
+        let mut client = LocalRegistry::default();
+        client.scan(registry.path()).unwrap();
+
+        let mut db = Connection::with_archive(client);
+        let info = db
+            .search("crossbeam-utils", &Version::new(0, 8, 20), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::Found);
+        assert_eq!(info.version, "0.8.21");
+        let package = info.package.unwrap();
+        assert_eq!(
+            package.binary.as_deref(),
+            Some("librust-crossbeam-utils-dev")
+        );
+        assert_eq!(package.suite.as_deref(), Some("registry"));
+
+        let info = db
+            .search("crossbeam-channel", &Version::new(0, 5, 15), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::Outdated);
+        let info = db
+            .search_new("crossbeam-channel", &Version::new(0, 5, 15), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::NotFound);
+    }
+
+    #[test]
+    fn find_installed_packages() {
+        let mut client = LocalRegistry::default();
+        client.parse_dpkg_status(
+            "\
+Package: librust-rand-core-dev
+Status: install ok installed
+Source: rust-rand-core
+Version: 0.6.4-2
+Provides: librust-rand-core-0-dev (= 0.6.4-2),
+ librust-rand-core-0.6-dev (= 0.6.4-2)
+
+Package: librust-serde-dev
+Status: deinstall ok config-files
+Version: 1.0.219-1
+
+Package: librust-rand-core-0.5-dev
+Status: install ok installed
+Source: rust-rand-core-0.5 (0.5.1-1)
+Version: 0.5.1-1+b1
+",
+        );
+
+        let mut db = Connection::with_archive(client);
+        let info = db
+            .search("rand_core", &Version::new(0, 5, 1), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::Found);
+        assert_eq!(info.version, "0.5.1");
+        let package = info.package.unwrap();
+        assert_eq!(package.source.as_deref(), Some("rust-rand-core-0.5"));
+        assert_eq!(package.suite.as_deref(), Some("installed"));
+
+        let info = db
+            .search("rand_core", &Version::new(0, 6, 1), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::Found);
+        assert_eq!(info.version, "0.6.4");
+
+        let info = db
+            .search("serde", &Version::new(1, 0, 200), None, true)
+            .unwrap();
+        assert_eq!(info.status, PkgStatus::NotFound);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::args::Args;
+use crate::debian::Pkg;
+use crate::debversion::DebVersion;
+use crate::errors::*;
+use crate::graph::Graph;
+use petgraph::EdgeDirection;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::Write;
+
+/// The outcome of resolving the dependency graph against Debian
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// The Debian versions picked for each crate, in the order they were reached
+    pub picks: Vec<Pick>,
+    pub failures: Vec<Unsatisfied>,
+    /// Crates whose Debian status couldn't be determined, so it's unknown if
+    /// they would resolve
+    pub unknown: Vec<Unknown>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pick {
+    pub name: String,
+    /// Version in Cargo.lock
+    pub locked: Version,
+    /// Versions used in Debian, one per semver-incompatible requirement
+    pub debian: BTreeSet<Version>,
+    /// The additional APT index the versions were taken from, if not the archive
+    pub origin: Option<String>,
+}
+
+/// A dependency that can't be satisfied with the crates packaged in Debian
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Unsatisfied {
+    pub name: String,
+    pub req: VersionReq,
+    /// Versions of the crate packaged in Debian
+    pub available: Vec<Version>,
+    /// The additional APT index the versions were taken from, if not the archive
+    pub origin: Option<String>,
+    /// Packages leading from a workspace member to the dependency, as `name vX.Y.Z`
+    pub chain: Vec<String>,
+}
+
+/// A dependency that couldn't be looked up, e.g. because the database wasn't
+/// reachable or the lookups were interrupted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Unknown {
+    pub name: String,
+    /// Version in Cargo.lock
+    pub locked: Version,
+    /// Why the lookup failed
+    pub reason: String,
+    /// Packages leading from a workspace member to the dependency, as `name vX.Y.Z`
+    pub chain: Vec<String>,
+}
+
+impl Resolution {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Versions of a crate a build in the release that was looked up could use,
+// most recent first, along with the APT index they were found in instead
+fn debian_versions(pkg: &Pkg) -> (Vec<Version>, Option<String>) {
+    let Some(deb) = pkg.debinfo.as_ref().filter(|deb| deb.in_release) else {
+        return (Vec::new(), None);
+    };
+
+    let mut versions = deb
+        .candidates
+        .iter()
+        .filter_map(|candidate| candidate.version.parse::<DebVersion>().ok())
+        .filter_map(|debversion| debversion.to_semver().ok())
+        .collect::<Vec<_>>();
+    versions.dedup();
+    (versions, deb.origin.clone())
+}
+
+/// Resolve the dependencies of the workspace using only crates packaged in Debian,
+/// like debcargo would during a build
+///
+/// The dependencies of the picked versions are approximated with the ones of
+/// the version in Cargo.lock. Crates found in an additional APT index are
+/// used too, their picks and failures are marked with the index. Crates that
+/// couldn't be looked up are reported as unknown, not as failures.
+pub fn simulate(graph: &Graph) -> Resolution {
+    let mut resolution = Resolution::default();
+    let mut picks: HashMap<NodeIndex, usize> = HashMap::new();
+    // how each package was reached, to explain failures
+    let mut parents: HashMap<NodeIndex, Option<NodeIndex>> = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    for root in &graph.roots {
+        let idx = graph.nodes[root];
+        if parents.insert(idx, None).is_none() {
+            queue.push_back(idx);
+        }
+    }
+
+    while let Some(idx) = queue.pop_front() {
+        let mut edges = graph
+            .graph
+            .edges_directed(idx, EdgeDirection::Outgoing)
+            .collect::<Vec<_>>();
+        // ensure a consistent output ordering
+        edges.sort_by_key(|edge| &graph.graph[edge.target()].id);
+
+        for edge in edges {
+            let target = edge.target();
+            let dep = &graph.graph[target];
+            // crates we don't intend to package aren't needed for the build
+            if dep.annotation.is_some() {
+                continue;
+            }
+
+            // path dependencies are built from the same source package, only
+            // crates from a registry need to be packaged
+            if dep.source.is_some() && dep.debinfo.is_none() {
+                let unknown = Unknown {
+                    name: dep.name.clone(),
+                    locked: dep.version.clone(),
+                    reason: dep
+                        .lookup_error
+                        .clone()
+                        .unwrap_or_else(|| "not looked up".to_string()),
+                    chain: chain(graph, &parents, idx, target),
+                };
+                if !resolution
+                    .unknown
+                    .iter()
+                    .any(|u| u.name == unknown.name && u.locked == unknown.locked)
+                {
+                    resolution.unknown.push(unknown);
+                }
+            } else if dep.source.is_some() {
+                let req = &edge.weight().req;
+                let (versions, origin) = debian_versions(dep);
+                let Some(version) = versions.iter().find(|v| req.matches(v)) else {
+                    let failure = Unsatisfied {
+                        name: dep.name.clone(),
+                        req: req.clone(),
+                        available: versions,
+                        origin,
+                        chain: chain(graph, &parents, idx, target),
+                    };
+                    if !resolution.failures.contains(&failure) {
+                        resolution.failures.push(failure);
+                    }
+                    continue;
+                };
+
+                let pick = *picks.entry(target).or_insert_with(|| {
+                    resolution.picks.push(Pick {
+                        name: dep.name.clone(),
+                        locked: dep.version.clone(),
+                        debian: BTreeSet::new(),
+                        origin: origin.clone(),
+                    });
+                    resolution.picks.len() - 1
+                });
+                resolution.picks[pick].debian.insert(version.clone());
+            }
+
+            if let Entry::Vacant(entry) = parents.entry(target) {
+                entry.insert(Some(idx));
+                queue.push_back(target);
+            }
+        }
+    }
+
+    resolution
+}
+
+// The path from a workspace member to the dependency `to` of `from`
+fn chain(
+    graph: &Graph,
+    parents: &HashMap<NodeIndex, Option<NodeIndex>>,
+    from: NodeIndex,
+    to: NodeIndex,
+) -> Vec<String> {
+    let mut chain = vec![to];
+    let mut next = Some(from);
+    while let Some(idx) = next {
+        chain.push(idx);
+        next = parents[&idx];
+    }
+
+    chain
+        .iter()
+        .rev()
+        .map(|idx| {
+            let pkg = &graph.graph[*idx];
+            format!("{} v{}", pkg.name, pkg.version)
+        })
+        .collect()
+}
+
+pub fn print<W: Write>(args: &Args, resolution: &Resolution, writer: &mut W) -> Result<(), Error> {
+    if args.json {
+        for pick in &resolution.picks {
+            writeln!(writer, "{}", serde_json::to_string(pick)?)?;
+        }
+        for failure in &resolution.failures {
+            writeln!(writer, "{}", serde_json::to_string(failure)?)?;
+        }
+        for unknown in &resolution.unknown {
+            writeln!(writer, "{}", serde_json::to_string(unknown)?)?;
+        }
+        return Ok(());
+    }
+
+    for pick in &resolution.picks {
+        let debian = pick
+            .debian
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        write!(
+            writer,
+            "{} v{} -> {}",
+            pick.name,
+            pick.locked,
+            debian.join(", ")
+        )?;
+        match &pick.origin {
+            Some(origin) => writeln!(writer, " (from {origin})")?,
+            None => writeln!(writer)?,
+        }
+    }
+
+    for failure in &resolution.failures {
+        let available = if failure.available.is_empty() {
+            "not in debian".to_string()
+        } else {
+            let versions = failure
+                .available
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>();
+            let archive = failure.origin.as_deref().unwrap_or("debian");
+            format!("{archive} has {}", versions.join(", "))
+        };
+        writeln!(
+            writer,
+            "unsatisfiable: {} {} ({available})",
+            failure.name, failure.req
+        )?;
+        writeln!(writer, "    {}", failure.chain.join(" -> "))?;
+    }
+
+    for unknown in &resolution.unknown {
+        writeln!(
+            writer,
+            "unknown: {} v{} (lookup failed: {})",
+            unknown.name, unknown.locked, unknown.reason
+        )?;
+        writeln!(writer, "    {}", unknown.chain.join(" -> "))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pick, Unsatisfied, print, simulate};
+    use crate::apt::AptIndex;
+    use crate::args::Args;
+    use crate::tree::tests::{
+        leak_mock_connection, mock_responses, new_mock_connection, registry_metadata,
+    };
+    use crate::{debian, graph};
+    use anyhow::Error;
+    use clap::Parser;
+    use semver::{Version, VersionReq};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn report_unsatisfiable_dependencies() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w"]);
+        let metadata = registry_metadata(&[("b", "^1"), ("c", ">=0.4")])?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+
+        let resolution = simulate(&graph);
+        assert!(!resolution.is_ok());
+        assert_eq!(
+            resolution.picks,
+            vec![Pick {
+                name: "c".to_string(),
+                locked: Version::new(1, 0, 0),
+                debian: BTreeSet::from([Version::new(0, 4, 5)]),
+                origin: None,
+            }]
+        );
+        assert_eq!(
+            resolution.failures,
+            vec![Unsatisfied {
+                name: "b".to_string(),
+                req: VersionReq::parse("^1")?,
+                available: vec![Version::new(2, 1, 0)],
+                origin: None,
+                chain: vec!["cargo-test v1.0.0".to_string(), "b v1.0.0".to_string()],
+            }]
+        );
+        assert_eq!(resolution.unknown, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn report_failed_lookups_as_unknown() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--simulate-build"]);
+        let metadata = registry_metadata(&[("b", "^1"), ("c", ">=0.4")])?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        // the database fails to answer for b
+        let new_connection = || -> Result<_, Error> {
+            let responses = mock_responses()
+                .into_iter()
+                .filter(|(_, params, _)| !params[0].ends_with("-b-dev") && params[0] != "rust-b")
+                .collect();
+            Ok(leak_mock_connection(responses))
+        };
+        debian::populate(&mut graph, &args.lookup_options(), &new_connection)?;
+
+        let resolution = simulate(&graph);
+        assert!(resolution.is_ok());
+        assert_eq!(resolution.unknown.len(), 1);
+        assert_eq!(resolution.unknown[0].name, "b");
+
+        let mut buffer = Vec::new();
+        print(&args, &resolution, &mut buffer)?;
+        let output = String::from_utf8(buffer)?;
+        assert!(
+            output.contains("unknown: b v1.0.0 (lookup failed: Unmocked SQL query"),
+            "{output}"
+        );
+        assert!(!output.contains("unsatisfiable"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn mark_picks_from_apt_indices() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--simulate-build"]);
+        let metadata = registry_metadata(&[("c", "^1")])?;
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        let new_connection = || -> Result<_, Error> {
+            let index = AptIndex::parse(
+                "http://apt.example.com/Packages",
+                "Package: librust-c-dev\nSource: rust-c\nVersion: 1.2.0-1\n",
+            );
+            Ok(new_mock_connection()?.with_apt_indices(vec![index]))
+        };
+        debian::populate(&mut graph, &args.lookup_options(), &new_connection)?;
+
+        let resolution = simulate(&graph);
+        let mut buffer = Vec::new();
+        print(&args, &resolution, &mut buffer)?;
+        assert_eq!(
+            String::from_utf8(buffer)?,
+            "b v1.0.0 -> 2.1.0\nc v1.0.0 -> 1.2.0 (from http://apt.example.com/Packages)\n"
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,5 @@
-#![allow(clippy::too_many_arguments)]
-
 use crate::args::{Args, Charset};
-use crate::debian::Pkg;
+use crate::debian::{PackagingProgress, Pkg};
 use crate::errors::*;
 use crate::format::{self, Pattern};
 use crate::graph::Graph;
@@ -62,39 +60,60 @@ pub fn print<W: Write>(args: &Args, graph: &Graph, writer: &mut W) -> Result<(),
         Prefix::Indent
     };
 
-    if args.duplicates {
-        for (i, package) in find_duplicates(graph).iter().enumerate() {
-            if i != 0 {
-                writeln!(writer)?;
-            }
-
-            let root = &graph.graph[graph.nodes[*package]];
-            print_tree(
-                graph, root, &format, direction, symbols, prefix, args.all, args.json, writer,
-            )?;
+    let mut pruned = HashSet::new();
+    for spec in &args.prune {
+        pruned.extend(find_packages(spec, graph)?);
+    }
+    for idx in graph.graph.node_indices() {
+        let package = &graph.graph[idx];
+        if args.crates.get(&package.name).is_some_and(|c| c.prune) {
+            pruned.insert(&package.id);
         }
+    }
+
+    let mut roots = if args.duplicates {
+        find_duplicates(graph)
     } else {
-        let roots = match &args.package {
+        match &args.package {
             Some(package) => vec![find_package(package, graph)?],
             None => graph.roots.iter().collect(),
-        };
-        for (i, root) in roots.into_iter().enumerate() {
-            if i != 0 {
-                println!();
-            }
+        }
+    };
+
+    let keep = args
+        .status
+        .as_ref()
+        .map(|statuses| find_matching(graph, &roots, &pruned, statuses, direction, args.all));
+    if let Some(keep) = &keep {
+        roots.retain(|root| keep.contains(root));
+    }
+
+    let settings = Settings {
+        format,
+        direction,
+        symbols,
+        prefix,
+        all: args.all,
+        json: args.json,
+        max_depth: args.depth,
+        pruned,
+        keep,
+    };
 
-            let root = &graph.graph[graph.nodes[root]];
-            print_tree(
-                graph, root, &format, direction, symbols, prefix, args.all, args.json, writer,
-            )?;
+    for (i, root) in roots.into_iter().enumerate() {
+        if i != 0 {
+            writeln!(writer)?;
         }
+
+        let root = &graph.graph[graph.nodes[root]];
+        print_tree(graph, root, &settings, writer)?;
     }
 
     Ok(())
 }
 
-fn find_package<'a>(package: &str, graph: &'a Graph) -> Result<&'a PackageId, Error> {
-    let mut it = package.split(':');
+fn find_packages<'a>(spec: &str, graph: &'a Graph) -> Result<Vec<&'a PackageId>, Error> {
+    let mut it = spec.split(':');
     let name = it.next().unwrap();
     let version = it
         .next()
@@ -115,15 +134,28 @@ fn find_package<'a>(package: &str, graph: &'a Graph) -> Result<&'a PackageId, Er
             continue;
         }
 
-        candidates.push(package);
+        candidates.push(&package.id);
     }
 
     if candidates.is_empty() {
-        Err(anyhow!("no crates found for package `{}`", package))
-    } else if candidates.len() > 1 {
+        Err(anyhow!("no crates found for package `{}`", spec))
+    } else {
+        // ensure a consistent output ordering
+        candidates.sort();
+        Ok(candidates)
+    }
+}
+
+fn find_package<'a>(package: &str, graph: &'a Graph) -> Result<&'a PackageId, Error> {
+    let candidates = find_packages(package, graph)?;
+
+    if candidates.len() > 1 {
         let specs = candidates
             .iter()
-            .map(|p| format!("{}:{}", p.name, p.version))
+            .map(|id| {
+                let p = &graph.graph[graph.nodes[*id]];
+                format!("{}:{}", p.name, p.version)
+            })
             .collect::<Vec<_>>()
             .join(", ");
         Err(anyhow!(
@@ -132,7 +164,7 @@ fn find_package<'a>(package: &str, graph: &'a Graph) -> Result<&'a PackageId, Er
             specs,
         ))
     } else {
-        Ok(&candidates[0].id)
+        Ok(candidates[0])
     }
 }
 
@@ -158,15 +190,64 @@ fn find_duplicates(graph: &Graph) -> Vec<&PackageId> {
     duplicates
 }
 
-fn print_tree<'a, W: Write>(
+// find all packages that either have one of the requested statuses, or lead to
+// such a package through dependencies that are going to be displayed
+fn find_matching<'a>(
     graph: &'a Graph,
-    root: &'a Pkg,
-    format: &Pattern,
+    roots: &[&PackageId],
+    pruned: &HashSet<&PackageId>,
+    statuses: &[PackagingProgress],
+    direction: EdgeDirection,
+    all: bool,
+) -> HashSet<&'a PackageId> {
+    let mut queue = graph
+        .graph
+        .node_indices()
+        .filter(|idx| {
+            let package = &graph.graph[*idx];
+            !pruned.contains(&package.id) && statuses.contains(&package.packaging_status())
+        })
+        .collect::<Vec<_>>();
+    let mut matching = queue
+        .iter()
+        .map(|idx| &graph.graph[*idx].id)
+        .collect::<HashSet<_>>();
+
+    while let Some(idx) = queue.pop() {
+        for parent in graph.graph.neighbors_directed(idx, direction.opposite()) {
+            let package = &graph.graph[parent];
+            if pruned.contains(&package.id) {
+                continue;
+            }
+            // the dependencies of this package are truncated in the output
+            if !all && !package.show_dependencies() && !roots.contains(&&package.id) {
+                continue;
+            }
+            if matching.insert(&package.id) {
+                queue.push(parent);
+            }
+        }
+    }
+
+    matching
+}
+
+struct Settings<'a> {
+    format: Pattern,
     direction: EdgeDirection,
-    symbols: &Symbols,
+    symbols: &'static Symbols,
     prefix: Prefix,
     all: bool,
     json: bool,
+    max_depth: Option<usize>,
+    pruned: HashSet<&'a PackageId>,
+    keep: Option<HashSet<&'a PackageId>>,
+}
+
+fn print_tree<'a, W: Write>(
+    graph: &'a Graph,
+    root: &'a Pkg,
+    settings: &Settings<'a>,
     writer: &mut W,
 ) -> Result<(), Error> {
     let mut visited_deps = HashSet::new();
@@ -175,12 +256,7 @@ fn print_tree<'a, W: Write>(
     print_package(
         graph,
         root,
-        format,
-        direction,
-        symbols,
-        prefix,
-        all,
-        json,
+        settings,
         &mut visited_deps,
         &mut levels_continue,
         writer,
@@ -190,20 +266,16 @@ fn print_tree<'a, W: Write>(
 fn print_package<'a, W: Write>(
     graph: &'a Graph,
     package: &'a Pkg,
-    format: &Pattern,
-    direction: EdgeDirection,
-    symbols: &Symbols,
-    prefix: Prefix,
-    all: bool,
-    json: bool,
+    settings: &Settings<'a>,
     visited_deps: &mut HashSet<&'a PackageId>,
     levels_continue: &mut Vec<bool>,
     writer: &mut W,
 ) -> Result<(), Error> {
+    let symbols = settings.symbols;
     let treeline = {
         let mut line = "".to_string();
         line.push_str(&format!(" {} ", &package.packaging_status()));
-        match prefix {
+        match settings.prefix {
             Prefix::Depth => line.push_str(&format!("{}", levels_continue.len())),
             Prefix::Indent => {
                 if let Some((last_continues, rest)) = levels_continue.split_last() {
@@ -225,18 +297,21 @@ fn print_package<'a, W: Write>(
         line
     };
 
-    if json {
+    if settings.json {
         writeln!(
             writer,
             "{}",
             format::json::display(package, levels_continue.len())?
         )?;
     } else {
-        let pkg_status_s = format::human::display(format, package)?;
+        let pkg_status_s = format::human::display(&settings.format, package)?;
         writeln!(writer, "{treeline}{pkg_status_s}")?;
     }
 
-    if !all && !package.show_dependencies() && !levels_continue.is_empty()
+    if !settings.all && !package.show_dependencies() && !levels_continue.is_empty()
+        || settings
+            .max_depth
+            .is_some_and(|depth| levels_continue.len() >= depth)
         || !visited_deps.insert(&package.id)
     {
         return Ok(());
@@ -250,12 +325,7 @@ fn print_package<'a, W: Write>(
         print_dependencies(
             graph,
             package,
-            format,
-            direction,
-            symbols,
-            prefix,
-            all,
-            json,
+            settings,
             visited_deps,
             levels_continue,
             *kind,
@@ -269,12 +339,7 @@ fn print_package<'a, W: Write>(
 fn print_dependencies<'a, W: Write>(
     graph: &'a Graph,
     package: &'a Pkg,
-    format: &Pattern,
-    direction: EdgeDirection,
-    symbols: &Symbols,
-    prefix: Prefix,
-    all: bool,
-    json: bool,
+    settings: &Settings<'a>,
     visited_deps: &mut HashSet<&'a PackageId>,
     levels_continue: &mut Vec<bool>,
     kind: DependencyKind,
@@ -282,15 +347,23 @@ fn print_dependencies<'a, W: Write>(
 ) -> Result<(), Error> {
     let idx = graph.nodes[&package.id];
     let mut deps = vec![];
-    for edge in graph.graph.edges_directed(idx, direction) {
-        if *edge.weight() != kind {
+    for edge in graph.graph.edges_directed(idx, settings.direction) {
+        if edge.weight().kind != kind {
             continue;
         }
 
-        let dep = match direction {
+        let dep = match settings.direction {
             EdgeDirection::Incoming => &graph.graph[edge.source()],
             EdgeDirection::Outgoing => &graph.graph[edge.target()],
         };
+        if settings.pruned.contains(&dep.id) {
+            continue;
+        }
+        if let Some(keep) = &settings.keep
+            && !keep.contains(&dep.id)
+        {
+            continue;
+        }
         deps.push(dep);
     }
 
@@ -301,7 +374,7 @@ fn print_dependencies<'a, W: Write>(
     // ensure a consistent output ordering
     deps.sort_by_key(|p| &p.id);
 
-    if !json {
+    if !settings.json {
         let name = match kind {
             DependencyKind::Normal => None,
             DependencyKind::Build => Some("[build-dependencies]"),
@@ -309,7 +382,7 @@ fn print_dependencies<'a, W: Write>(
             _ => unreachable!(),
         };
 
-        if let Prefix::Indent = prefix
+        if let Prefix::Indent = settings.prefix
             && let Some(name) = name
         {
             // start with padding used by packaging status icons
@@ -317,7 +390,11 @@ fn print_dependencies<'a, W: Write>(
 
             // print tree graph parts
             for continues in &**levels_continue {
-                let c = if *continues { symbols.down } else { " " };
+                let c = if *continues {
+                    settings.symbols.down
+                } else {
+                    " "
+                };
                 write!(writer, "{c}   ")?;
             }
 
@@ -332,12 +409,7 @@ fn print_dependencies<'a, W: Write>(
         print_package(
             graph,
             dependency,
-            format,
-            direction,
-            symbols,
-            prefix,
-            all,
-            json,
+            settings,
             &mut visited_deps.clone(),
             levels_continue,
             writer,
@@ -349,16 +421,19 @@ fn print_dependencies<'a, W: Write>(
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use anyhow::Error;
     use cargo_metadata::Metadata;
     use clap::Parser;
+    use serde_json::{Value, json};
+    use std::fs;
     use strip_ansi_escapes::strip;
 
     use super::print;
     use crate::{
         args::Args,
+        config::{Annotation, AnnotationKind, CrateConfig},
         db::{
             Connection,
             tests::{MockClient, mock_connection},
@@ -372,7 +447,7 @@ mod tests {
         let metadata: Metadata = serde_json::from_str(include_str!(
             "../tests/data/cargo_metadata_without_loop.json"
         ))?;
-        let graph = graph::build(&args, metadata)?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
         let mut buffer = Vec::new();
 
         print(&args, &graph, &mut buffer)?;
@@ -390,7 +465,7 @@ mod tests {
         let args = Args::parse_from(["debstatus"]);
         let metadata: Metadata =
             serde_json::from_str(include_str!("../tests/data/cargo_metadata_with_loop.json"))?;
-        let graph = graph::build(&args, metadata)?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
         let mut buffer = Vec::new();
 
         print(&args, &graph, &mut buffer)?;
@@ -410,7 +485,7 @@ mod tests {
         let metadata: Metadata = serde_json::from_str(include_str!(
             "../tests/data/cargo_metadata_with_common_dependency.json"
         ))?;
-        let graph = graph::build(&args, metadata)?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
         let mut buffer = Vec::new();
 
         print(&args, &graph, &mut buffer)?;
@@ -427,100 +502,577 @@ mod tests {
         Ok(())
     }
 
-    fn new_mock_connection() -> Result<Connection<MockClient<'static>>, Error> {
-        // TODO: the tmp dir created by this test isn't deleted.
-        // Change ownership so that it is.
-        let tmpdir = Box::leak(Box::new(
-            tempfile::tempdir()
-                .expect("could not create a temporary directory for the cache")
-                .keep(),
-        ));
-        let mocked_responses = Box::leak(Box::new(vec![
+    #[test]
+    fn print_tree_with_max_depth() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "--depth", "1"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_without_loop.json"
+        ))?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
+        let mut buffer = Vec::new();
+
+        print(&args, &graph, &mut buffer)?;
+
+        let expected = r#" 🪏  cargotest v0.1.0 (in workspace, /tmp/cargotest)
+ 🔴 └── crossbeam-channel v0.5.15
+"#;
+        assert_eq!(String::from_utf8(buffer)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_tree_with_pruned_package() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--prune", "b"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_common_dependency.json"
+        ))?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
+        let mut buffer = Vec::new();
+
+        print(&args, &graph, &mut buffer)?;
+
+        let expected = r#" 🪏  cargo-test v0.1.0 (in workspace, /private/tmp/cargo-test)
+ 🪏  ├── a v0.1.0 (in workspace, /private/tmp/cargo-test/a)
+ 🪏  └── d v0.1.0 (in workspace, /private/tmp/cargo-test/d)
+"#;
+        assert_eq!(String::from_utf8(buffer)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_tree_with_custom_format() -> Result<(), Error> {
+        let args = Args::parse_from([
+            "debstatus",
+            "--no-indent",
+            "-f",
+            "{n:<18}|{v:>7}|{s:^9}|{dependents}|{f}",
+        ]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_without_loop.json"
+        ))?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
+        let mut buffer = Vec::new();
+
+        print(&args, &graph, &mut buffer)?;
+
+        let expected = r#" 🪏  cargotest         |  0.1.0|missing-in-workspace|0|
+ 🔴 crossbeam-channel | 0.5.15| missing |1|default,std
+ 🔴 crossbeam-utils   | 0.8.21| missing |1|std
+"#;
+        assert_eq!(String::from_utf8(buffer)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_padded_icons() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "--no-indent", "-f", "{i:>4}|{n}"]);
+        let metadata: Metadata = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_without_loop.json"
+        ))?;
+        let graph = graph::build(&args.graph_options(), metadata)?;
+        let mut buffer = Vec::new();
+
+        print(&args, &graph, &mut buffer)?;
+
+        // emoji take up two columns
+        let expected = " \
+ 🪏   🪏 |cargotest
+ 🔴   🔴|crossbeam-channel
+ 🔴   🔴|crossbeam-utils\n";
+        assert_eq!(String::from_utf8(buffer)?, expected);
+        Ok(())
+    }
+
+    /// The flat dependencies fixture, with `b` and `c` coming from crates.io
+    /// and the given version requirements of their dependents
+    pub(crate) fn registry_metadata(reqs: &[(&str, &str)]) -> Result<Metadata, Error> {
+        let mut metadata: Value = serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_flat_dependencies.json"
+        ))?;
+        for package in metadata["packages"].as_array_mut().unwrap() {
+            if package["name"] == "b" || package["name"] == "c" {
+                package["source"] = json!("registry+https://github.com/rust-lang/crates.io-index");
+            }
+            for dep in package["dependencies"].as_array_mut().unwrap() {
+                if let Some((_, req)) = reqs.iter().find(|(name, _)| dep["name"] == *name) {
+                    dep["req"] = json!(req);
+                }
+            }
+        }
+        Ok(serde_json::from_value(metadata)?)
+    }
+
+    /// Add a dev-dependency of d on c 1.x to a flat dependencies fixture, which
+    /// isn't part of the graph with `--edges normal,build`
+    pub(crate) fn hidden_dev_dependency(metadata: Metadata) -> Result<Metadata, Error> {
+        let mut metadata = serde_json::to_value(metadata)?;
+        let c = "path+file:///private/tmp/cargo-test/c#1.0.0";
+        for package in metadata["packages"].as_array_mut().unwrap() {
+            if package["name"] == "d" {
+                let mut dep = package["dependencies"][0].clone();
+                dep["name"] = json!("c");
+                dep["req"] = json!("^1");
+                dep["kind"] = json!("dev");
+                package["dependencies"].as_array_mut().unwrap().push(dep);
+            }
+        }
+        for node in metadata["resolve"]["nodes"].as_array_mut().unwrap() {
+            if node["id"] == "path+file:///private/tmp/cargo-test/d#1.0.0" {
+                node["dependencies"] = json!([c]);
+                node["deps"] = json!([{
+                    "name": "c",
+                    "pkg": c,
+                    "dep_kinds": [{"kind": "dev", "target": null}],
+                }]);
+            }
+        }
+        Ok(serde_json::from_value(metadata)?)
+    }
+
+    pub(crate) type MockedResponse = (&'static str, Vec<&'static str>, Vec<Vec<&'static str>>);
+
+    pub(crate) fn new_mock_connection() -> Result<Connection<MockClient<'static>>, Error> {
+        Ok(leak_mock_connection(mock_responses()))
+    }
+
+    // The database as seen by the crates of the `cargo_metadata_with_flat_dependencies` fixture
+    pub(crate) fn mock_responses() -> Vec<MockedResponse> {
+        vec![
             (
-                "SELECT version::text FROM sources WHERE source in ($1, $2) AND release='sid';",
-                vec!["rust-a", "rust-a-1"],
-                vec![vec!["1.0.0-2"]],
+                "SELECT DISTINCT version::text, source::text, ''::text, release::text, component::text FROM sources WHERE (source = $1 OR source ~ $2) AND release=$3;",
+                vec!["rust-a", "^rust-a-[0-9.]+$", "sid"],
+                vec![vec!["1.0.0-2", "rust-a", "", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM packages WHERE package in ($1, $2) AND release='sid';",
-                vec!["librust-a-dev", "librust-a-1-dev"],
-                vec![vec!["1.0.0-2"]],
+                "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+                vec!["librust-a-dev", "^librust-a-[0-9.]+-dev$", "sid"],
+                vec![vec!["1.0.0-2", "rust-a", "librust-a-dev", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM sources WHERE source in ($1, $2) AND release='sid';",
-                vec!["rust-b", "rust-b-1"],
-                vec![vec!["2.1.0-1"]],
+                "SELECT DISTINCT version::text, source::text, ''::text, release::text, component::text FROM sources WHERE (source = $1 OR source ~ $2) AND release=$3;",
+                vec!["rust-b", "^rust-b-[0-9.]+$", "sid"],
+                vec![vec!["2.1.0-1", "rust-b", "", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM packages WHERE package in ($1, $2) AND release='sid';",
-                vec!["librust-b-dev", "librust-b-1-dev"],
-                vec![vec!["2.1.0-1"]],
+                "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+                vec!["librust-b-dev", "^librust-b-[0-9.]+-dev$", "sid"],
+                vec![vec!["2.1.0-1", "rust-b", "librust-b-dev", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM sources WHERE source in ($1, $2) AND release='sid';",
-                vec!["rust-c", "rust-c-1"],
-                vec![vec!["0.4.5-1"]],
+                "SELECT DISTINCT version::text, source::text, ''::text, release::text, component::text FROM sources WHERE (source = $1 OR source ~ $2) AND release=$3;",
+                vec!["rust-c", "^rust-c-[0-9.]+$", "sid"],
+                vec![vec!["0.4.5-1", "rust-c", "", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM packages WHERE package in ($1, $2) AND release='sid';",
-                vec!["librust-c-dev", "librust-c-1-dev"],
-                vec![vec!["0.4.5-1"]],
+                "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+                vec!["librust-c-dev", "^librust-c-[0-9.]+-dev$", "sid"],
+                vec![vec!["0.4.5-1", "rust-c", "librust-c-dev", "sid", "main"]],
             ),
             (
-                "SELECT version::text FROM sources WHERE source in ($1, $2) AND release='sid';",
-                vec!["rust-d", "rust-d-1"],
+                "SELECT DISTINCT version::text, source::text, ''::text, release::text, component::text FROM sources WHERE (source = $1 OR source ~ $2) AND release=$3;",
+                vec!["rust-d", "^rust-d-[0-9.]+$", "sid"],
                 vec![],
             ),
             (
-                "SELECT version::text FROM new_sources WHERE source in ($1, $2);",
-                vec!["rust-d", "rust-d-1"],
+                "SELECT DISTINCT version::text, source::text, ''::text, 'new'::text, component::text FROM new_sources WHERE (source = $1 OR source ~ $2);",
+                vec!["rust-d", "^rust-d-[0-9.]+$"],
                 vec![],
             ),
             (
-                "SELECT version::text FROM packages WHERE package in ($1, $2) AND release='sid';",
-                vec!["librust-d-dev", "librust-d-1-dev"],
+                "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+                vec!["librust-d-dev", "^librust-d-[0-9.]+-dev$", "sid"],
                 vec![],
             ),
             (
-                "SELECT version::text FROM sources WHERE source in ($1, $2) AND release='sid';",
-                vec!["rust-cargo-test", "rust-cargo-test-1"],
+                "SELECT DISTINCT version::text, source::text, ''::text, release::text, component::text FROM sources WHERE (source = $1 OR source ~ $2) AND release=$3;",
+                vec!["rust-cargo-test", "^rust-cargo-test-[0-9.]+$", "sid"],
                 vec![],
             ),
             (
-                "SELECT version::text FROM new_sources WHERE source in ($1, $2);",
-                vec!["rust-cargo-test", "rust-cargo-test-1"],
+                "SELECT DISTINCT version::text, source::text, ''::text, 'new'::text, component::text FROM new_sources WHERE (source = $1 OR source ~ $2);",
+                vec!["rust-cargo-test", "^rust-cargo-test-[0-9.]+$"],
+                vec![],
+            ),
+            (
+                "SELECT DISTINCT version::text, source::text, package::text, release::text, component::text FROM packages WHERE (package = $1 OR package ~ $2) AND release=$3;",
+                vec![
+                    "librust-cargo-test-dev",
+                    "^librust-cargo-test-[0-9.]+-dev$",
+                    "sid",
+                ],
+                vec![],
+            ),
+            (
+                "SELECT id::text, title::text FROM bugs WHERE source = $1 AND severity in ('serious', 'grave', 'critical') AND status != 'done';",
+                vec!["rust-a"],
+                vec![vec!["1000002", "rust-a: FTBFS with newer rustc"]],
+            ),
+            (
+                "SELECT migration_policy_verdict::text, COALESCE(reason, '')::text FROM migration_excuses WHERE source = $1;",
+                vec!["rust-a"],
+                vec![vec!["REJECTED_PERMANENTLY", ""]],
+            ),
+            (
+                "SELECT id::text, title::text FROM bugs WHERE source = $1 AND severity in ('serious', 'grave', 'critical') AND status != 'done';",
+                vec!["rust-b"],
+                vec![],
+            ),
+            (
+                "SELECT migration_policy_verdict::text, COALESCE(reason, '')::text FROM migration_excuses WHERE source = $1;",
+                vec!["rust-b"],
+                vec![vec!["PASS", ""]],
+            ),
+            (
+                "SELECT id::text, title::text FROM bugs WHERE source = $1 AND severity in ('serious', 'grave', 'critical') AND status != 'done';",
+                vec!["rust-c"],
                 vec![],
             ),
             (
-                "SELECT version::text FROM packages WHERE package in ($1, $2) AND release='sid';",
-                vec!["librust-cargo-test-dev", "librust-cargo-test-1-dev"],
+                "SELECT migration_policy_verdict::text, COALESCE(reason, '')::text FROM migration_excuses WHERE source = $1;",
+                vec!["rust-c"],
+                vec![vec![
+                    "REJECTED_TEMPORARILY",
+                    "autopkgtest regression on arm64",
+                ]],
+            ),
+            (
+                "SELECT wnpp.id::text, wnpp.type::text, wnpp.source::text, COALESCE(bugs.owner, '')::text FROM wnpp LEFT JOIN bugs ON bugs.id = wnpp.id WHERE wnpp.source in ($1, $2);",
+                vec!["rust-d", "rust-d-1"],
                 vec![],
             ),
-        ]));
-        Ok(mock_connection(tmpdir, mocked_responses))
+            (
+                "SELECT wnpp.id::text, wnpp.type::text, wnpp.source::text, COALESCE(bugs.owner, '')::text FROM wnpp LEFT JOIN bugs ON bugs.id = wnpp.id WHERE wnpp.source in ($1, $2);",
+                vec!["rust-cargo-test", "rust-cargo-test-1"],
+                vec![],
+            ),
+        ]
+    }
+
+    pub(crate) fn leak_mock_connection(
+        responses: Vec<MockedResponse>,
+    ) -> Connection<MockClient<'static>> {
+        // TODO: the tmp dir created by this test isn't deleted.
+        // Change ownership so that it is.
+        let tmpdir = Box::leak(Box::new(
+            tempfile::tempdir()
+                .expect("could not create a temporary directory for the cache")
+                .keep(),
+        ));
+        mock_connection(tmpdir, Box::leak(Box::new(responses)))
+    }
+
+    /// The flat dependencies fixture, with every crate in the workspace
+    pub(crate) fn flat_metadata() -> Result<Metadata, Error> {
+        Ok(serde_json::from_str(include_str!(
+            "../tests/data/cargo_metadata_with_flat_dependencies.json"
+        ))?)
+    }
+
+    // Look up the crates of `metadata` in the mocked database and print them with `args`
+    fn lookup_and_print(
+        args: &Args,
+        metadata: Metadata,
+        responses: impl Fn() -> Vec<MockedResponse> + Sync,
+    ) -> Result<String, Error> {
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        debian::populate(&mut graph, &args.lookup_options(), &|| {
+            Ok(leak_mock_connection(responses()))
+        })?;
+        let mut buffer = Vec::new();
+        print(args, &graph, &mut buffer)?;
+        Ok(String::from_utf8(strip(buffer))?)
     }
 
     #[test]
     fn print_dependency_status_in_debian() -> Result<(), Error> {
         let args = Args::parse_from(["debstatus", "-w"]);
-        let metadata: Metadata = serde_json::from_str(include_str!(
-            "../tests/data/cargo_metadata_with_flat_dependencies.json"
-        ))?;
-        let mut graph = graph::build(&args, metadata)?;
-        debian::populate(&mut graph, &args, &new_mock_connection)?;
-        let mut buffer = Vec::new();
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
 
-        print(&args, &graph, &mut buffer)?;
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+    ├── a v1.0.0 (in debian) (in workspace, /private/tmp/cargo-test/a)
+ 🔽 ├── b v1.0.0 (newer, 2.1.0 in debian) (in workspace, /private/tmp/cargo-test/b)
+ ⌛ ├── c v1.0.0 (outdated, 0.4.5 in debian) (in workspace, /private/tmp/cargo-test/c)
+ 🪏  └── d v1.0.0 (in workspace, /private/tmp/cargo-test/d)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_wnpp_bugs_of_missing_crates() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--status", "missing-in-workspace"]);
+        let responses = || {
+            let mut responses = mock_responses();
+            for (query, params, rows) in &mut responses {
+                if query.contains("FROM wnpp") && params[0] == "rust-d" {
+                    *rows = vec![vec![
+                        "1000001",
+                        "ITP",
+                        "rust-d",
+                        "Jane Doe <jane@example.com>",
+                    ]];
+                }
+            }
+            responses
+        };
+        let output = lookup_and_print(&args, flat_metadata()?, responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+ 🪏  └── d v1.0.0 (ITP #1000001 by Jane Doe <jane@example.com>) (in workspace, /private/tmp/cargo-test/d)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
 
-        let output = String::from_utf8(strip(buffer)).unwrap();
+    #[test]
+    fn print_dependency_matching_requirements() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w"]);
+        let metadata = registry_metadata(&[("b", "^1"), ("c", ">=0.4")])?;
+        let output = lookup_and_print(&args, metadata, mock_responses)?;
 
         let expected = " \
  🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
     ├── a v1.0.0 (in debian) (in workspace, /private/tmp/cargo-test/a)
+ 🔽 ├── b v1.0.0 (newer, 2.1.0 in debian)
+    ├── c v1.0.0 (0.4.5 in debian, Cargo.lock is stricter than needed)
+ 🪏  └── d v1.0.0 (in workspace, /private/tmp/cargo-test/d)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_dependency_satisfied_by_patch() -> Result<(), Error> {
+        let checkout = tempfile::tempdir()?;
+        let write_patch = |dir: &str, patch: &str| -> Result<(), Error> {
+            let patches = checkout.path().join("src").join(dir).join("debian/patches");
+            fs::create_dir_all(&patches)?;
+            fs::write(patches.join("series"), "relax-deps.patch\n")?;
+            fs::write(patches.join("relax-deps.patch"), patch)?;
+            Ok(())
+        };
+        write_patch(
+            "b",
+            "\
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -10,3 +10,3 @@
+ [dependencies.c]
+-version = \"1\"
++version = \">= 0.4\"
+",
+        )?;
+        // only crates from a registry are packaged, not the workspace
+        write_patch(
+            "cargo-test",
+            "\
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -10,3 +10,3 @@
+ [dependencies.b]
+-version = \"1\"
++version = \">= 2\"
+",
+        )?;
+
+        let args = Args::parse_from([
+            "debstatus",
+            "-w",
+            "--debcargo-conf",
+            checkout.path().to_str().unwrap(),
+        ]);
+        // move the dependency on c from the workspace to b
+        let mut metadata = serde_json::to_value(registry_metadata(&[("b", "^1"), ("c", "^1")])?)?;
+        let c = "path+file:///private/tmp/cargo-test/c#1.0.0";
+        let mut dependency = Value::Null;
+        for package in metadata["packages"].as_array_mut().unwrap() {
+            if package["name"] == "cargo-test" {
+                let deps = package["dependencies"].as_array_mut().unwrap();
+                let idx = deps.iter().position(|dep| dep["name"] == "c").unwrap();
+                dependency = deps.remove(idx);
+            }
+        }
+        let mut deps = Vec::new();
+        for node in metadata["resolve"]["nodes"].as_array_mut().unwrap() {
+            if node["id"] == "path+file:///private/tmp/cargo-test#1.0.0" {
+                node["dependencies"]
+                    .as_array_mut()
+                    .unwrap()
+                    .retain(|id| id != c);
+                let node_deps = node["deps"].as_array_mut().unwrap();
+                let idx = node_deps.iter().position(|dep| dep["pkg"] == c).unwrap();
+                deps.push(node_deps.remove(idx));
+            }
+        }
+        for package in metadata["packages"].as_array_mut().unwrap() {
+            if package["name"] == "b" {
+                package["dependencies"] = json!([dependency]);
+            }
+        }
+        for node in metadata["resolve"]["nodes"].as_array_mut().unwrap() {
+            if node["id"] == "path+file:///private/tmp/cargo-test/b#1.0.0" {
+                node["dependencies"] = json!([c]);
+                node["deps"] = json!(deps);
+            }
+        }
+
+        let output = lookup_and_print(&args, serde_json::from_value(metadata)?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+    ├── a v1.0.0 (in debian) (in workspace, /private/tmp/cargo-test/a)
+ 🔽 ├── b v1.0.0 (newer, 2.1.0 in debian)
+    │   └── c v1.0.0 (0.4.5 in debian, satisfied via Debian patch)
+ 🪏  └── d v1.0.0 (in workspace, /private/tmp/cargo-test/d)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_testing_migration_risks() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--testing"]);
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+    ├── a v1.0.0 (in debian) (at risk: RC bug #1000002: rust-a: FTBFS with newer rustc; migration blocked: REJECTED_PERMANENTLY) (in workspace, /private/tmp/cargo-test/a)
  🔽 ├── b v1.0.0 (newer, 2.1.0 in debian) (in workspace, /private/tmp/cargo-test/b)
- ⌛ ├── c v1.0.0 (outdated, 0.4.5 in debian) (in workspace, /private/tmp/cargo-test/c)
+ ⌛ ├── c v1.0.0 (outdated, 0.4.5 in debian) (at risk: migration blocked: autopkgtest regression on arm64) (in workspace, /private/tmp/cargo-test/c)
  🪏  └── d v1.0.0 (in workspace, /private/tmp/cargo-test/d)\n";
         assert_eq!(output, expected);
         Ok(())
     }
+
+    #[test]
+    fn recommend_patching_dependents_in_debian() -> Result<(), Error> {
+        let mut args = Args::parse_from(["debstatus", "-w"]);
+        let metadata = flat_metadata()?;
+        let find = |graph: &graph::Graph, name: &str| {
+            graph
+                .graph
+                .node_indices()
+                .find(|idx| graph.graph[*idx].name == name)
+                .unwrap()
+        };
+
+        // the workspace isn't in debian, so b needs a compat package
+        let mut graph = graph::build(&args.graph_options(), metadata.clone())?;
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+        let b = find(&graph, "b");
+        assert_eq!(
+            graph.graph[b].recommended_action().as_deref(),
+            Some("add rust-b-1")
+        );
+
+        // unless it's only used by crates that are packaged already
+        let mut graph = graph::build(&args.graph_options(), metadata.clone())?;
+        let (root, a, b) = (
+            find(&graph, "cargo-test"),
+            find(&graph, "a"),
+            find(&graph, "b"),
+        );
+        let edge = graph.graph.find_edge(root, b).unwrap();
+        let weight = graph.graph.remove_edge(edge).unwrap();
+        graph.graph.add_edge(a, b, weight);
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+        assert_eq!(
+            graph.graph[b].recommended_action().as_deref(),
+            Some("patch dependents to 2.1.0")
+        );
+
+        // or it's configured to
+        args.crates.insert(
+            "b".to_string(),
+            CrateConfig {
+                patch_dependents: true,
+                ..Default::default()
+            },
+        );
+        let mut graph = graph::build(&args.graph_options(), metadata)?;
+        debian::populate(&mut graph, &args.lookup_options(), &new_mock_connection)?;
+        let b = find(&graph, "b");
+        assert_eq!(
+            graph.graph[b].recommended_action().as_deref(),
+            Some("patch dependents to 2.1.0")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_crates_in_preparation() -> Result<(), Error> {
+        let checkout = tempfile::tempdir()?;
+        let debian = checkout.path().join("src/d/debian");
+        fs::create_dir_all(&debian)?;
+        fs::write(
+            debian.join("changelog"),
+            "rust-d (1.0.0-1) UNRELEASED; urgency=medium\n",
+        )?;
+
+        let args = Args::parse_from([
+            "debstatus",
+            "-w",
+            "--debcargo-conf",
+            checkout.path().to_str().unwrap(),
+            "--status",
+            "in-preparation",
+        ]);
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+ 🚧 └── d v1.0.0 (1.0.0-1 in debcargo-conf, UNRELEASED) (in workspace, /private/tmp/cargo-test/d)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_only_requested_statuses() -> Result<(), Error> {
+        let args = Args::parse_from(["debstatus", "-w", "--status", "needs-update,needs-patching"]);
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+ 🔽 ├── b v1.0.0 (newer, 2.1.0 in debian) (in workspace, /private/tmp/cargo-test/b)
+ ⌛ └── c v1.0.0 (outdated, 0.4.5 in debian) (in workspace, /private/tmp/cargo-test/c)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_conditional_format_segments() -> Result<(), Error> {
+        let args = Args::parse_from([
+            "debstatus",
+            "-w",
+            "--no-indent",
+            "-f",
+            "{n}{?d: (debian {d}{?new:, NEW queue})}",
+        ]);
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test
+    a (debian 1.0.0)
+ 🔽 b (debian 2.1.0)
+ ⌛ c (debian 0.4.5)
+ 🪏  d\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn print_ignored_dependencies() -> Result<(), Error> {
+        let mut args = Args::parse_from(["debstatus", "-w", "--status", "patched-out"]);
+        args.ignore = vec![Annotation {
+            name: "c".to_string(),
+            version: Some("^1".parse()?),
+            reason: "not needed on linux".to_string(),
+            kind: AnnotationKind::PatchedOut,
+        }];
+        let output = lookup_and_print(&args, flat_metadata()?, mock_responses)?;
+
+        let expected = " \
+ 🪏  cargo-test v1.0.0 (in workspace, /private/tmp/cargo-test)
+ 🩹 └── c v1.0.0 (patched out: not needed on linux) (in workspace, /private/tmp/cargo-test/c)\n";
+        assert_eq!(output, expected);
+        Ok(())
+    }
 }
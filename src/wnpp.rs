@@ -0,0 +1,118 @@
+use crate::errors::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A bug in the Work-Needing and Prospective Packages pseudo-package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WnppBug {
+    pub id: u32,
+    /// ITP, RFP, O, ITA, RFA or RFH
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub source: String,
+    pub owner: Option<String>,
+}
+
+impl WnppBug {
+    /// Build a bug from the columns `id, type, source, owner`
+    pub(crate) fn from_row(row: &[String]) -> Result<WnppBug, Error> {
+        let [id, kind, source, owner] = row else {
+            bail!("Unexpected wnpp row: {row:?}");
+        };
+        Ok(WnppBug {
+            id: id.parse().context("invalid wnpp bug number")?,
+            kind: kind.clone(),
+            source: source.clone(),
+            owner: Some(owner.clone()).filter(|o| !o.is_empty()),
+        })
+    }
+
+    /// Someone is already working on this package
+    pub fn in_progress(&self) -> bool {
+        matches!(self.kind.as_str(), "ITP" | "ITA")
+    }
+
+    /// Pick the most relevant bug, preferring ones that are being worked on
+    pub fn best(mut bugs: Vec<WnppBug>) -> Option<WnppBug> {
+        bugs.sort_by_key(|bug| (!bug.in_progress(), bug.id));
+        bugs.into_iter().next()
+    }
+}
+
+impl fmt::Display for WnppBug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} #{}", self.kind, self.id)?;
+        if let Some(owner) = &self.owner {
+            write!(f, " by {owner}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a BTS export with one `source: TYPE bug [owner]` line per bug, e.g.
+/// `rust-foo: ITP 1234567 Jane Doe <jane@example.com>`
+pub fn read_file(path: &Path) -> Result<Vec<WnppBug>, Error> {
+    let buf =
+        fs::read_to_string(path).with_context(|| format!("error reading wnpp file {path:?}"))?;
+    parse(&buf).with_context(|| format!("error parsing wnpp file {path:?}"))
+}
+
+fn parse(buf: &str) -> Result<Vec<WnppBug>, Error> {
+    let mut bugs = vec![];
+    for line in buf.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (source, rest) = line
+            .split_once(':')
+            .with_context(|| format!("missing ':' in line: {line:?}"))?;
+        let mut fields = rest.trim().splitn(3, ' ');
+        let (Some(kind), Some(id)) = (fields.next(), fields.next()) else {
+            bail!("expected a bug type and number: {line:?}");
+        };
+        bugs.push(WnppBug {
+            id: id
+                .trim_start_matches('#')
+                .parse()
+                .with_context(|| format!("invalid bug number: {line:?}"))?,
+            kind: kind.to_string(),
+            source: source.trim().to_string(),
+            owner: fields.next().map(|owner| owner.trim().to_string()),
+        });
+    }
+    Ok(bugs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WnppBug, parse};
+
+    #[test]
+    fn parse_wnpp_file() {
+        let bugs = parse(
+            "\
+# source: type bug [owner]
+rust-foo: RFP 1000001
+rust-foo: ITP 1000002 Jane Doe <jane@example.com>
+rust-bar: O #999
+",
+        )
+        .unwrap();
+        assert_eq!(bugs.len(), 3);
+        assert_eq!(bugs[2].id, 999);
+        assert_eq!(bugs[2].owner, None);
+
+        let best = WnppBug::best(bugs[..2].to_vec()).unwrap();
+        assert_eq!(
+            best.to_string(),
+            "ITP #1000002 by Jane Doe <jane@example.com>"
+        );
+
+        assert!(parse("rust-foo ITP 1").is_err());
+        assert!(parse("rust-foo: ITP x").is_err());
+    }
+}